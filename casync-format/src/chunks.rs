@@ -2,25 +2,17 @@ use std;
 use std::io;
 use std::io::Read;
 
-use anyhow::Error;
-use anyhow::ensure;
-
 use super::Chunk;
 use super::FlatReader;
+use super::IndexKind;
+use super::error::Error;
 use super::fetcher::Fetcher;
 use super::read_index;
 
-/// guess the `.castr` (relative) path from the `.caidx` path, and fetch both
-pub fn from_index<F: 'static + Fetcher>(
-    idx: &str,
-    fetcher: F,
-) -> Result<impl Read, Error> {
-    ensure!(
-        idx.ends_with(".caidx"),
-        "index must have a .caidx extension, not {:?}",
-        idx
-    );
-    let prefix = format!("{}.castr", &idx[..idx.len() - ".caidx".len()]);
+/// guess the `.castr` (relative) path from the `.caidx` or `.caibx` path, and fetch both
+pub fn from_index<F: 'static + Fetcher>(idx: &str, fetcher: F) -> Result<impl Read, Error> {
+    let kind = IndexKind::from_path(idx)?;
+    let prefix = format!("{}.castr", &idx[..idx.len() - kind.extension().len()]);
     from_paths(idx, prefix, fetcher)
 }
 
@@ -39,11 +31,25 @@ pub fn from_paths<F: 'static + Fetcher, TS: ToString>(
 
 /// use a pre-fetched `index` and pre-configured `fetcher`
 /// which can fetch chunks given `abcd/abcdefg012[..]30.cacnk`.
+///
+/// Each chunk must be as long as the index says it is.
 pub fn from_chunks<F: 'static + Fetcher>(chunks: Vec<Chunk>, mut fetcher: F) -> impl Read {
-    FlatReader::new(chunks.into_iter().map(move |c| -> Result<_, io::Error> {
-        let fetched = fetcher.fetch(&c.format_id())?;
-        let fetched = zstd::stream::decode_all(io::Cursor::new(fetched))?;
-        c.check(&fetched)?;
-        Ok(fetched)
+    let mut start = 0;
+    FlatReader::new(chunks.into_iter().map(move |c| {
+        let data = load_chunk(&mut fetcher, &c)?;
+        c.check_len(start, &data)?;
+        start = c.offset;
+        Ok(data)
     }))
 }
+
+/// fetch, decompress, and check a chunk, as `from_chunks` does
+pub fn load_chunk<F: Fetcher>(fetcher: &mut F, chunk: &Chunk) -> Result<Vec<u8>, Error> {
+    let compressed = fetcher
+        .fetch(&chunk.format_id())
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::ChunkMissing { id: chunk.id },
+            _ => Error::from(e),
+        })?;
+    chunk.decompress(&compressed)
+}
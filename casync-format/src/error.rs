@@ -0,0 +1,243 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::format::ChunkId;
+use crate::index::format_chunk_id;
+
+/// Everything which can go wrong reading, or writing, casync's formats.
+///
+/// Offsets are in bytes from the start of the stream (or file) being read,
+/// and are where the packet (or record) which was found to be bad starts.
+/// Errors from a `Stream` also have the `path` of the item it was reading,
+/// with any invalid utf-8 replaced.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// the fetcher couldn't find a chunk which an index references
+    ChunkMissing { id: ChunkId },
+    /// a chunk's content doesn't match its id
+    ChecksumMismatch { id: ChunkId, actual: ChunkId },
+    /// a chunk which isn't valid zstd
+    CorruptChunk { id: ChunkId, source: io::Error },
+    /// a chunk whose content is a different length to what the index says
+    ChunkSize {
+        id: ChunkId,
+        expected: u64,
+        actual: u64,
+    },
+    /// a packet, or index, with a type we don't know about
+    UnknownMagic {
+        magic: u64,
+        offset: u64,
+        path: Option<String>,
+    },
+    /// a packet which is bigger than we're prepared to load into memory
+    RecordTooLarge {
+        size: u64,
+        limit: u64,
+        offset: u64,
+        path: Option<String>,
+    },
+    /// the input ended part way through something
+    Truncated { offset: u64, path: Option<String> },
+    /// feature flags which we don't know about, or support
+    UnsupportedFeatureFlags { flags: u64 },
+    /// the input isn't valid, e.g. a goodbye table which disagrees with its directory
+    Malformed {
+        message: String,
+        offset: Option<u64>,
+        path: Option<String>,
+    },
+    /// a request which can't be done, e.g. writing a file outside of any directory
+    Invalid(String),
+    /// from the underlying reader, writer, or fetcher
+    Io(io::Error),
+}
+
+impl Error {
+    pub(crate) fn malformed<S: Into<String>>(message: S) -> Error {
+        Error::Malformed {
+            message: message.into(),
+            offset: None,
+            path: None,
+        }
+    }
+
+    /// fill in where we were, if the error didn't already know
+    pub(crate) fn at(self, offset: u64) -> Error {
+        match self {
+            Error::Malformed {
+                message,
+                offset: None,
+                path,
+            } => Error::Malformed {
+                message,
+                offset: Some(offset),
+                path,
+            },
+            Error::Io(e) if io::ErrorKind::UnexpectedEof == e.kind() => {
+                Error::Truncated { offset, path: None }
+            }
+            other => other,
+        }
+    }
+
+    /// fill in which item we were reading, for errors about the input
+    pub(crate) fn in_path(mut self, item: String) -> Error {
+        match &mut self {
+            Error::UnknownMagic { path, .. }
+            | Error::RecordTooLarge { path, .. }
+            | Error::Truncated { path, .. }
+            | Error::Malformed { path, .. } => {
+                path.get_or_insert(item);
+            }
+            _ => (),
+        }
+        self
+    }
+}
+
+impl Error {
+    /// where the problem is in the input, if it's known
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Error::UnknownMagic { offset, .. }
+            | Error::RecordTooLarge { offset, .. }
+            | Error::Truncated { offset, .. } => Some(*offset),
+            Error::Malformed { offset, .. } => *offset,
+            _ => None,
+        }
+    }
+
+    /// the item which was being read, if it's known
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Error::UnknownMagic { path, .. }
+            | Error::RecordTooLarge { path, .. }
+            | Error::Truncated { path, .. }
+            | Error::Malformed { path, .. } => path.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ChunkMissing { id } => {
+                return write!(f, "chunk missing: {}", format_chunk_id(id));
+            }
+            Error::ChecksumMismatch { id, actual } => {
+                return write!(
+                    f,
+                    "checksum mismatch: chunk {} has the content of {}",
+                    format_chunk_id(id),
+                    format_chunk_id(actual)
+                );
+            }
+            Error::CorruptChunk { id, source } => {
+                return write!(f, "corrupt chunk {}: {}", format_chunk_id(id), source);
+            }
+            Error::ChunkSize {
+                id,
+                expected,
+                actual,
+            } => {
+                return write!(
+                    f,
+                    "chunk {} is {} bytes, but the index says {}",
+                    format_chunk_id(id),
+                    actual,
+                    expected
+                );
+            }
+            Error::UnsupportedFeatureFlags { flags } => {
+                return write!(f, "unsupported feature flags: {:#x}", flags);
+            }
+            Error::Invalid(message) => return f.write_str(message),
+            Error::Io(e) => return e.fmt(f),
+            Error::UnknownMagic { magic, .. } => write!(f, "unrecognised magic {:x}", magic)?,
+            Error::RecordTooLarge { size, limit, .. } => write!(
+                f,
+                "refusing to support records over {} bytes, was: {}",
+                limit, size
+            )?,
+            Error::Truncated { .. } => f.write_str("unexpected end of input")?,
+            Error::Malformed { message, .. } => f.write_str(message)?,
+        }
+
+        // so it can be found with e.g. `dd`
+        if let Some(offset) = self.offset() {
+            write!(f, ", at byte {}", offset)?;
+        }
+        if let Some(path) = self.path() {
+            write!(f, ", in {:?}", path)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::CorruptChunk { source, .. } => Some(source),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    /// unwraps our own errors, which have been through a `Read`
+    fn from(e: io::Error) -> Error {
+        if e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = e.into_inner().expect("just checked");
+            return *inner.downcast::<Error>().expect("just checked");
+        }
+        Error::Io(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    /// so our errors can pass through a `Read`, and be recovered
+    fn from(e: Error) -> io::Error {
+        let kind = match e {
+            Error::Io(inner) => return inner,
+            Error::ChunkMissing { .. } => io::ErrorKind::NotFound,
+            Error::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+            Error::Invalid(_) => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// return a `Malformed` error, like `anyhow::bail!`
+macro_rules! bail {
+    ($($arg:tt)+) => {
+        return Err($crate::error::Error::malformed(format!($($arg)+)))
+    };
+}
+
+/// return a `Malformed` error unless `cond`, like `anyhow::ensure!`
+macro_rules! ensure {
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            return Err($crate::error::Error::malformed(format!($($arg)+)));
+        }
+    };
+}
+
+/// return an `Invalid` error, for misuse, unless `cond`
+macro_rules! ensure_valid {
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            return Err($crate::error::Error::Invalid(format!($($arg)+)));
+        }
+    };
+}
+
+pub(crate) use bail;
+pub(crate) use ensure;
+pub(crate) use ensure_valid;
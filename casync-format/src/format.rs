@@ -1,56 +1,194 @@
-use anyhow::Error;
-use anyhow::bail;
+use bitflags::bitflags;
+
+use crate::error::Error;
 
 const ENTRY: u64 = 0x1396fabcea5bbb51;
 const USER: u64 = 0xf453131aaeeaccb3;
 const GROUP: u64 = 0x25eb6ac969396a52;
+const XATTR: u64 = 0xb8157091f80bc486;
+const ACL_USER: u64 = 0x297dc88b2ef12faf;
+const ACL_GROUP: u64 = 0x36f2acb56cb3dd0b;
+const ACL_GROUP_OBJ: u64 = 0x23047110441f38f3;
+const ACL_DEFAULT: u64 = 0xfe3eeda6823c8cd0;
+const ACL_DEFAULT_USER: u64 = 0xbdf03df9bd010a91;
+const ACL_DEFAULT_GROUP: u64 = 0xa0cb1168782d1f51;
+const FCAPS: u64 = 0xf7267db0afed0629;
+const SELINUX: u64 = 0x46faf0602fd26c59;
+const SYMLINK: u64 = 0x664a6fb6830e0d6c;
+const DEVICE: u64 = 0xac3dace369dfe643;
 const FILENAME: u64 = 0x6dbb6ebcb3161f0b;
 const PAYLOAD: u64 = 0x8b9e1d93d6dcffc9;
 const GOODBYE: u64 = 0xdfd35c5e8327c403;
+/// ours, and opt-in; upstream has no hardlinks, so can't read archives which contain them
+const HARDLINK: u64 = 0xf1566e4802bfa498;
+
+pub(crate) const GOODBYE_TAIL_MARKER: u64 = 0x57446fa533702943;
+pub(crate) const GOODBYE_HASH_KEY: (u64, u64) = (0x8574442b0f1d84b3, 0x2736ed30d1c22ec1);
 
 const INDEX: u64 = 0x96824d9c7b129ff9;
 const TABLE: u64 = 0xe75b9e112f17417d;
 
+pub(crate) const TABLE_TAIL_MARKER: u64 = 0x4b4f050e5549ecd1;
+
 pub type ChunkId = [u8; 32];
 
-#[derive(Eq, PartialEq, Debug)]
+bitflags! {
+    /// upstream's `CA_FORMAT_*`: what an index, or an entry, was made with
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+    pub struct FeatureFlags: u64 {
+        const WITH_16BIT_UIDS = 0x1;
+        const WITH_32BIT_UIDS = 0x2;
+        const WITH_USER_NAMES = 0x4;
+        const WITH_SEC_TIME = 0x8;
+        const WITH_USEC_TIME = 0x10;
+        const WITH_NSEC_TIME = 0x20;
+        const WITH_2SEC_TIME = 0x40;
+        const WITH_READ_ONLY = 0x80;
+        const WITH_PERMISSIONS = 0x100;
+        const WITH_SYMLINKS = 0x200;
+        const WITH_DEVICE_NODES = 0x400;
+        const WITH_FIFOS = 0x800;
+        const WITH_SOCKETS = 0x1000;
+
+        // DOS file flags
+        const WITH_FLAG_HIDDEN = 0x2000;
+        const WITH_FLAG_SYSTEM = 0x4000;
+        const WITH_FLAG_ARCHIVE = 0x8000;
+
+        // chattr flags
+        const WITH_FLAG_APPEND = 0x10000;
+        const WITH_FLAG_NOATIME = 0x20000;
+        const WITH_FLAG_COMPR = 0x40000;
+        const WITH_FLAG_NOCOW = 0x80000;
+        const WITH_FLAG_NODUMP = 0x100000;
+        const WITH_FLAG_DIRSYNC = 0x200000;
+        const WITH_FLAG_IMMUTABLE = 0x400000;
+        const WITH_FLAG_SYNC = 0x800000;
+        const WITH_FLAG_NOCOMP = 0x1000000;
+        const WITH_FLAG_PROJINHERIT = 0x2000000;
+
+        // btrfs
+        const WITH_SUBVOLUME = 0x4000000;
+        const WITH_SUBVOLUME_RO = 0x8000000;
+
+        const WITH_XATTRS = 0x10000000;
+        const WITH_ACL = 0x20000000;
+        const WITH_SELINUX = 0x40000000;
+        const WITH_FCAPS = 0x80000000;
+        const WITH_QUOTA_PROJID = 0x100000000;
+
+        const EXCLUDE_FILE = 0x1000000000000000;
+        /// chunk ids are SHA-512/256, instead of SHA-256
+        const SHA512_256 = 0x2000000000000000;
+        const EXCLUDE_SUBMOUNTS = 0x4000000000000000;
+        const EXCLUDE_NODUMP = 0x8000000000000000;
+    }
+}
+
+impl FeatureFlags {
+    /// the flags from a file, which must all be ones we know about
+    pub fn from_raw(raw: u64) -> Result<FeatureFlags, Error> {
+        FeatureFlags::from_bits(raw).ok_or(Error::UnsupportedFeatureFlags {
+            flags: raw & !FeatureFlags::all().bits(),
+        })
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum StreamMagic {
     Entry,
     User,
     Group,
+    Xattr,
+    AclUser,
+    AclGroup,
+    AclGroupObj,
+    AclDefault,
+    AclDefaultUser,
+    AclDefaultGroup,
+    Fcaps,
+    Selinux,
+    Symlink,
+    Device,
     Name,
     Data,
     Bye,
+    Hardlink,
 }
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum IndexMagic {
     Index,
     Table,
 }
 
 impl StreamMagic {
-    pub fn from(val: u64) -> Result<Self, Error> {
+    /// the magic for this number from a stream, if we know it
+    pub fn from(val: u64) -> Option<Self> {
         use self::StreamMagic::*;
-        Ok(match val {
+        Some(match val {
             ENTRY => Entry,
             USER => User,
             GROUP => Group,
+            XATTR => Xattr,
+            ACL_USER => AclUser,
+            ACL_GROUP => AclGroup,
+            ACL_GROUP_OBJ => AclGroupObj,
+            ACL_DEFAULT => AclDefault,
+            ACL_DEFAULT_USER => AclDefaultUser,
+            ACL_DEFAULT_GROUP => AclDefaultGroup,
+            FCAPS => Fcaps,
+            SELINUX => Selinux,
+            SYMLINK => Symlink,
+            DEVICE => Device,
             FILENAME => Name,
             PAYLOAD => Data,
             GOODBYE => Bye,
-            _ => bail!("unrecognised stream magic: {:x}", val),
+            HARDLINK => Hardlink,
+            _ => return None,
         })
     }
+
+    /// the number which appears in the stream
+    pub fn value(self) -> u64 {
+        use self::StreamMagic::*;
+        match self {
+            Entry => ENTRY,
+            User => USER,
+            Group => GROUP,
+            Xattr => XATTR,
+            AclUser => ACL_USER,
+            AclGroup => ACL_GROUP,
+            AclGroupObj => ACL_GROUP_OBJ,
+            AclDefault => ACL_DEFAULT,
+            AclDefaultUser => ACL_DEFAULT_USER,
+            AclDefaultGroup => ACL_DEFAULT_GROUP,
+            Fcaps => FCAPS,
+            Selinux => SELINUX,
+            Symlink => SYMLINK,
+            Device => DEVICE,
+            Name => FILENAME,
+            Data => PAYLOAD,
+            Bye => GOODBYE,
+            Hardlink => HARDLINK,
+        }
+    }
 }
 
 impl IndexMagic {
-    pub fn from(val: u64) -> Result<Self, Error> {
+    pub fn from(val: u64) -> Option<Self> {
         use self::IndexMagic::*;
-        Ok(match val {
+        Some(match val {
             INDEX => Index,
             TABLE => Table,
-            _ => bail!("unrecognised index magic: {:x}", val),
+            _ => return None,
         })
     }
+
+    pub(crate) fn value(self) -> u64 {
+        match self {
+            IndexMagic::Index => INDEX,
+            IndexMagic::Table => TABLE,
+        }
+    }
 }
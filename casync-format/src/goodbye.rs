@@ -0,0 +1,222 @@
+use std::hash::Hasher;
+
+use siphasher::sip::SipHasher24;
+
+use crate::error::Error;
+use crate::error::bail;
+use crate::error::ensure;
+use crate::format::GOODBYE_HASH_KEY;
+use crate::format::GOODBYE_TAIL_MARKER;
+
+const ITEM_LEN: usize = 8 * 3;
+
+/// A reference to one child of a directory, from the directory's goodbye table.
+///
+/// Ordered by `hash` first, as the table is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GoodbyeItem {
+    /// `hash_name` of the child's name
+    pub hash: u64,
+    /// distance back from the start of the goodbye packet to the child's `Name` packet
+    pub offset: u64,
+    /// length of the child, from its `Name` to the end of its content
+    pub size: u64,
+}
+
+/// The payload of a `Bye` packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Goodbye {
+    /// arranged as a binary search tree on `hash`;
+    /// the children of `items[n]` are `items[2n + 1]` and `items[2n + 2]`
+    pub items: Vec<GoodbyeItem>,
+    /// distance back from the start of the goodbye packet to the directory's `Entry`
+    pub entry_offset: u64,
+    /// length of the whole goodbye packet, including its header
+    pub size: u64,
+}
+
+/// The key for looking up `name` in a goodbye table.
+pub fn hash_name(name: &[u8]) -> u64 {
+    let mut hasher = SipHasher24::new_with_keys(GOODBYE_HASH_KEY.0, GOODBYE_HASH_KEY.1);
+    hasher.write(name);
+    hasher.finish()
+}
+
+impl Goodbye {
+    /// decode a `Bye` packet's payload, i.e. excluding the header
+    pub fn parse(payload: &[u8]) -> Result<Goodbye, Error> {
+        ensure!(
+            !payload.is_empty() && payload.len().is_multiple_of(ITEM_LEN),
+            "goodbye table isn't a whole number of items: {}",
+            payload.len()
+        );
+
+        let mut items: Vec<GoodbyeItem> = payload
+            .chunks_exact(ITEM_LEN)
+            .map(|item| GoodbyeItem {
+                offset: leu64(&item[..8]),
+                size: leu64(&item[8..16]),
+                hash: leu64(&item[16..]),
+            })
+            .collect();
+
+        let tail = items.pop().expect("checked non-empty");
+        ensure!(
+            GOODBYE_TAIL_MARKER == tail.hash,
+            "goodbye table tail marker missing: {:x}",
+            tail.hash
+        );
+
+        Ok(Goodbye {
+            items,
+            entry_offset: tail.offset,
+            size: tail.size,
+        })
+    }
+
+    /// Arrange some items into a goodbye table, which will start at `entry_offset`
+    /// bytes after the start of the directory's `Entry`.
+    pub fn from_items(mut items: Vec<GoodbyeItem>, entry_offset: u64) -> Goodbye {
+        items.sort();
+        let size = ((items.len() + 1) * ITEM_LEN) as u64 + 16;
+        let mut tree = items.clone();
+        fill_tree(&mut items.into_iter(), &mut tree, 0);
+        Goodbye {
+            items: tree,
+            entry_offset,
+            size,
+        }
+    }
+
+    /// the encoded payload, i.e. excluding the header
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity((self.items.len() + 1) * ITEM_LEN);
+        let tail = GoodbyeItem {
+            offset: self.entry_offset,
+            size: self.size,
+            hash: GOODBYE_TAIL_MARKER,
+        };
+        for item in self.items.iter().chain(Some(&tail)) {
+            ret.extend_from_slice(&item.offset.to_le_bytes());
+            ret.extend_from_slice(&item.size.to_le_bytes());
+            ret.extend_from_slice(&item.hash.to_le_bytes());
+        }
+        ret
+    }
+
+    /// true if the items are in the order a search relies on
+    pub fn is_search_tree(&self) -> bool {
+        let mut in_order = Vec::with_capacity(self.items.len());
+        walk_tree(&self.items, 0, &mut in_order);
+        in_order.windows(2).all(|pair| pair[0] <= pair[1])
+    }
+
+    /// All the items which could have this `hash`, i.e. which have a matching hash,
+    /// or are collisions which we'd have to check by name.
+    pub fn find(&self, hash: u64) -> Vec<&GoodbyeItem> {
+        let mut found = Vec::new();
+        find_in_tree(&self.items, 0, hash, &mut found);
+        found
+    }
+
+    /// Check the table describes `children`, a directory's actual contents.
+    ///
+    /// `start` is the absolute position of the goodbye packet, `entry` of the directory's `Entry`,
+    /// and `children` have their absolute `offset`s, in any order.
+    pub(crate) fn validate(
+        &self,
+        header_size: u64,
+        start: u64,
+        entry: u64,
+        children: &[GoodbyeItem],
+    ) -> Result<(), Error> {
+        ensure!(
+            header_size == self.size,
+            "goodbye table records its size as {}, but was {}",
+            self.size,
+            header_size
+        );
+        ensure!(
+            start - entry == self.entry_offset,
+            "goodbye table records its entry {} bytes back, but it was {}",
+            self.entry_offset,
+            start - entry
+        );
+        ensure!(
+            children.len() == self.items.len(),
+            "goodbye table lists {} items, but the directory had {}",
+            self.items.len(),
+            children.len()
+        );
+
+        let mut expected: Vec<GoodbyeItem> = children
+            .iter()
+            .map(|child| GoodbyeItem {
+                offset: start - child.offset,
+                ..*child
+            })
+            .collect();
+        expected.sort();
+
+        let mut actual = self.items.clone();
+        actual.sort();
+
+        for (expected, actual) in expected.iter().zip(actual.iter()) {
+            if expected != actual {
+                bail!(
+                    "goodbye table disagrees with directory: expected {:?}, found {:?}",
+                    expected,
+                    actual
+                );
+            }
+        }
+
+        ensure!(
+            self.is_search_tree(),
+            "goodbye table isn't sorted as a search tree"
+        );
+
+        Ok(())
+    }
+}
+
+fn fill_tree<I: Iterator<Item = GoodbyeItem>>(sorted: &mut I, tree: &mut [GoodbyeItem], i: usize) {
+    if i >= tree.len() {
+        return;
+    }
+    fill_tree(sorted, tree, 2 * i + 1);
+    tree[i] = sorted.next().expect("as many items as slots");
+    fill_tree(sorted, tree, 2 * i + 2);
+}
+
+fn walk_tree(tree: &[GoodbyeItem], i: usize, into: &mut Vec<u64>) {
+    if i >= tree.len() {
+        return;
+    }
+    walk_tree(tree, 2 * i + 1, into);
+    into.push(tree[i].hash);
+    walk_tree(tree, 2 * i + 2, into);
+}
+
+fn find_in_tree<'t>(tree: &'t [GoodbyeItem], i: usize, hash: u64, into: &mut Vec<&'t GoodbyeItem>) {
+    if i >= tree.len() {
+        return;
+    }
+
+    // equal hashes may be on either side of a match
+    if hash <= tree[i].hash {
+        find_in_tree(tree, 2 * i + 1, hash, into);
+    }
+    if hash == tree[i].hash {
+        into.push(&tree[i]);
+    }
+    if hash >= tree[i].hash {
+        find_in_tree(tree, 2 * i + 2, hash, into);
+    }
+}
+
+fn leu64(buf: &[u8]) -> u64 {
+    let mut arr = [0u8; 8];
+    arr.copy_from_slice(buf);
+    u64::from_le_bytes(arr)
+}
@@ -1,15 +1,21 @@
-use std;
 use std::fmt;
 use std::io;
 use std::io::Read;
 
-use anyhow::Error;
-use anyhow::bail;
-use anyhow::ensure;
+use sha2::Digest;
 
+use crate::chunker::discriminator_from_avg;
+use crate::error::Error;
+use crate::error::bail;
+use crate::error::ensure;
+use crate::error::ensure_valid;
 use crate::format::ChunkId;
+use crate::format::FeatureFlags;
 use crate::format::IndexMagic;
+use crate::stream::Tracked;
 
+/// The limits on chunk sizes, in bytes, which a stream was chunked with.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct ChunkSize {
     pub min: u64,
     pub avg: u64,
@@ -17,18 +23,106 @@ pub struct ChunkSize {
 }
 
 impl ChunkSize {
-    fn new(min: u64, avg: u64, max: u64) -> Result<ChunkSize, Error> {
-        ensure!(min >= 1, "minimum chunk size is too low");
-        ensure!(max <= 128 * 1024 * 1024, "maximum chunk size is too high");
-        ensure!(avg <= max && avg >= min, "avg chunk size is out of range");
+    pub fn new(min: u64, avg: u64, max: u64) -> Result<ChunkSize, Error> {
+        ensure_valid!(min >= 1, "minimum chunk size is too low");
+        ensure_valid!(max <= 128 * 1024 * 1024, "maximum chunk size is too high");
+        ensure_valid!(avg <= max && avg >= min, "avg chunk size is out of range");
+        // the chunker would never find a boundary
+        ensure_valid!(
+            discriminator_from_avg(avg) >= 1,
+            "avg chunk size is too low: {}",
+            avg
+        );
         Ok(ChunkSize { min, avg, max })
     }
+
+    /// upstream's default for `min` and `max`: a quarter, and four times, the `avg`
+    pub fn from_avg(avg: u64) -> Result<ChunkSize, Error> {
+        ChunkSize::new(avg / 4, avg, avg * 4)
+    }
+}
+
+impl Default for ChunkSize {
+    /// upstream's default, 64KiB on average
+    fn default() -> ChunkSize {
+        ChunkSize::from_avg(64 * 1024).expect("valid default")
+    }
+}
+
+/// What the stream an index describes contains, as told by its extension.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IndexKind {
+    /// a `.caidx`, for a `catar`
+    Catar,
+    /// a `.caibx`, for any other file, e.g. a disk image
+    Blob,
+}
+
+impl IndexKind {
+    pub fn from_path(path: &str) -> Result<IndexKind, Error> {
+        Ok(if path.ends_with(IndexKind::Catar.extension()) {
+            IndexKind::Catar
+        } else if path.ends_with(IndexKind::Blob.extension()) {
+            IndexKind::Blob
+        } else {
+            return Err(Error::Invalid(format!(
+                "index must have a .caidx or .caibx extension, not {:?}",
+                path
+            )));
+        })
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            IndexKind::Catar => ".caidx",
+            IndexKind::Blob => ".caibx",
+        }
+    }
+}
+
+/// How chunk ids are calculated from their content, as an index's feature flags record.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ChunkDigest {
+    /// what an index without the `SHA512_256` flag uses
+    Sha256,
+    /// upstream's default, as its indexes, like `nums.caidx`, record
+    Sha512_256,
+}
+
+impl ChunkDigest {
+    pub fn from_flags(flags: FeatureFlags) -> ChunkDigest {
+        if flags.contains(FeatureFlags::SHA512_256) {
+            ChunkDigest::Sha512_256
+        } else {
+            ChunkDigest::Sha256
+        }
+    }
+
+    /// the feature flag to record in an index which uses this digest
+    pub fn flags(self) -> FeatureFlags {
+        match self {
+            ChunkDigest::Sha256 => FeatureFlags::empty(),
+            ChunkDigest::Sha512_256 => FeatureFlags::SHA512_256,
+        }
+    }
+
+    /// the id of a chunk with this (uncompressed) content
+    pub fn id(self, data: &[u8]) -> ChunkId {
+        let mut id = ChunkId::default();
+        match self {
+            ChunkDigest::Sha256 => id.copy_from_slice(&sha2::Sha256::digest(data)),
+            ChunkDigest::Sha512_256 => id.copy_from_slice(&sha2::Sha512_256::digest(data)),
+        }
+        id
+    }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub struct Chunk {
     pub offset: u64,
     pub id: ChunkId,
+    /// how `id` was calculated
+    pub digest: ChunkDigest,
 }
 
 impl fmt::Debug for Chunk {
@@ -51,16 +145,59 @@ pub fn format_chunk_id(id: &ChunkId) -> String {
     ret
 }
 
+/// the id from a path like `abcd/abcdefg012[..]30.cacnk`, as `format_chunk_id` writes
+pub fn parse_chunk_id(cacnk: &str) -> Option<ChunkId> {
+    let name = cacnk.rsplit('/').next()?.strip_suffix(".cacnk")?;
+    if name.len() != 2 * 32 || !name.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut id = ChunkId::default();
+    for (byte, hex) in id.iter_mut().zip(name.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+    }
+    Some(id)
+}
+
 impl Chunk {
     pub fn format_id(&self) -> String {
         format_chunk_id(&self.id)
     }
 
-    pub fn check(&self, data: &[u8]) -> io::Result<()> {
-        let actual = digest(data);
+    /// decompress the content of a `.cacnk`, and `check` it
+    pub fn decompress(&self, compressed: &[u8]) -> Result<Vec<u8>, Error> {
+        let data = zstd::stream::decode_all(compressed).map_err(|source| Error::CorruptChunk {
+            id: self.id,
+            source,
+        })?;
+        self.check(&data)?;
+        Ok(data)
+    }
+
+    /// check that `data`, the content of this chunk, fills the index from `start`,
+    /// the previous chunk's `offset`, to ours
+    pub fn check_len(&self, start: u64, data: &[u8]) -> Result<(), Error> {
+        let expected = self
+            .offset
+            .checked_sub(start)
+            .ok_or_else(|| Error::malformed(format!("chunk offsets go backwards at {}", start)))?;
+        if data.len() as u64 != expected {
+            return Err(Error::ChunkSize {
+                id: self.id,
+                expected,
+                actual: data.len() as u64,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check(&self, data: &[u8]) -> Result<(), Error> {
+        let actual = self.digest.id(data);
 
         if actual != self.id {
-            return Err(io::Error::new(io::ErrorKind::Other, "checksum mismatch"));
+            return Err(Error::ChecksumMismatch {
+                id: self.id,
+                actual,
+            });
         }
 
         Ok(())
@@ -68,6 +205,15 @@ impl Chunk {
 }
 
 pub fn read_index<R: Read>(mut from: R) -> Result<(ChunkSize, Vec<Chunk>), Error> {
+    let mut offset = 0;
+    let read = read_index_tracked(Tracked {
+        inner: &mut from,
+        offset: &mut offset,
+    });
+    read.map_err(|e| e.at(offset))
+}
+
+fn read_index_tracked<R: Read>(mut from: Tracked<R>) -> Result<(ChunkSize, Vec<Chunk>), Error> {
     {
         let header_size = leu64(&mut from)?;
         ensure!(
@@ -77,28 +223,40 @@ pub fn read_index<R: Read>(mut from: R) -> Result<(ChunkSize, Vec<Chunk>), Error
         );
     }
 
-    ensure!(
-        IndexMagic::Index == IndexMagic::from(leu64(&mut from)?)?,
-        "file magic number doesn't look like an index"
-    );
+    let magic = leu64(&mut from)?;
+    if Some(IndexMagic::Index) != IndexMagic::from(magic) {
+        return Err(Error::UnknownMagic {
+            magic,
+            offset: 8,
+            path: None,
+        });
+    }
 
-    leu64(&mut from)?; // feature_flags
+    let digest = ChunkDigest::from_flags(FeatureFlags::from_raw(leu64(&mut from)?)?);
     let chunk_size = {
         let min = leu64(&mut from)?;
         let avg = leu64(&mut from)?;
         let max = leu64(&mut from)?;
-        ChunkSize::new(min, avg, max)?
+        // not the caller's mistake, but the file's; the sizes start after the feature flags
+        ChunkSize::new(min, avg, max).map_err(|e| match e {
+            Error::Invalid(message) => Error::malformed(message).at(24),
+            other => other,
+        })?
     };
 
     ensure!(
-        std::u64::MAX == leu64(&mut from)?,
+        u64::MAX == leu64(&mut from)?,
         "table size should be u64::MAX"
     );
 
-    ensure!(
-        IndexMagic::Table == IndexMagic::from(leu64(&mut from)?)?,
-        "table magic missing"
-    );
+    let magic = leu64(&mut from)?;
+    if Some(IndexMagic::Table) != IndexMagic::from(magic) {
+        return Err(Error::UnknownMagic {
+            magic,
+            offset: 40,
+            path: None,
+        });
+    }
 
     let mut chunks = Vec::with_capacity(32);
 
@@ -115,7 +273,7 @@ pub fn read_index<R: Read>(mut from: R) -> Result<(ChunkSize, Vec<Chunk>), Error
             bail!("end of index marker, but not at end of file")
         }
 
-        chunks.push(Chunk { offset, id });
+        chunks.push(Chunk { offset, id, digest });
     }
 
     Ok((chunk_size, chunks))
@@ -135,11 +293,3 @@ fn leu64<R: Read>(mut from: R) -> io::Result<u64> {
     from.read_exact(&mut buf)?;
     Ok(u64::from_le_bytes(buf))
 }
-
-fn digest(data: &[u8]) -> ChunkId {
-    use sha2::Digest;
-    let digest = sha2::Sha512_256::digest(data);
-    let mut id = ChunkId::default();
-    id.copy_from_slice(&digest[..]);
-    id
-}
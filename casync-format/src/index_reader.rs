@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::chunks::load_chunk;
+use crate::fetcher::Fetcher;
+use crate::index::Chunk;
+
+const DEFAULT_CACHE_CHUNKS: usize = 4;
+
+/// `Read` and `Seek` over the stream an index describes, only fetching the chunks
+/// which are actually read, and keeping the last few of them around.
+pub struct IndexReader<F> {
+    chunks: Vec<Chunk>,
+    fetcher: F,
+    position: u64,
+    /// decoded chunks, by their position in `chunks`, most recently used last
+    cache: VecDeque<(usize, Vec<u8>)>,
+    cache_chunks: usize,
+}
+
+impl<F: Fetcher> IndexReader<F> {
+    /// `fetcher` is given chunk paths like `abcd/abcdefg012[..]30.cacnk`, as with `from_chunks`.
+    pub fn new(chunks: Vec<Chunk>, fetcher: F) -> IndexReader<F> {
+        IndexReader {
+            chunks,
+            fetcher,
+            position: 0,
+            cache: VecDeque::with_capacity(DEFAULT_CACHE_CHUNKS),
+            cache_chunks: DEFAULT_CACHE_CHUNKS,
+        }
+    }
+
+    /// keep up to `chunks` decoded chunks in memory (at least one)
+    pub fn with_cache_chunks(mut self, chunks: usize) -> IndexReader<F> {
+        self.cache_chunks = chunks.max(1);
+        self.cache.truncate(self.cache_chunks);
+        self
+    }
+
+    /// the length of the whole stream
+    pub fn len(&self) -> u64 {
+        self.chunks.last().map(|c| c.offset).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        0 == self.len()
+    }
+
+    /// where the chunk at `index` starts in the stream
+    fn chunk_start(&self, index: usize) -> u64 {
+        match index {
+            0 => 0,
+            _ => self.chunks[index - 1].offset,
+        }
+    }
+
+    fn chunk(&mut self, index: usize) -> io::Result<&[u8]> {
+        if let Some(found) = self.cache.iter().position(|(i, _)| *i == index) {
+            let hit = self.cache.remove(found).expect("just found");
+            self.cache.push_back(hit);
+        } else {
+            let chunk = &self.chunks[index];
+            let data = load_chunk(&mut self.fetcher, chunk)?;
+            chunk.check_len(self.chunk_start(index), &data)?;
+
+            if self.cache.len() >= self.cache_chunks {
+                self.cache.pop_front();
+            }
+            self.cache.push_back((index, data));
+        }
+
+        Ok(&self.cache.back().expect("just pushed").1)
+    }
+}
+
+impl<F: Fetcher> Read for IndexReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // chunk offsets are where each chunk ends, so find the first which ends after us
+        let index = self.chunks.partition_point(|c| c.offset <= self.position);
+        if index == self.chunks.len() || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let skip = (self.position - self.chunk_start(index)) as usize;
+        let data = &self.chunk(index)?[skip..];
+        let reading = buf.len().min(data.len());
+        buf[..reading].copy_from_slice(&data[..reading]);
+
+        self.position += reading as u64;
+        Ok(reading)
+    }
+}
+
+impl<F: Fetcher> Seek for IndexReader<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(to) => {
+                self.position = to;
+                return Ok(to);
+            }
+            SeekFrom::End(offset) => (self.len(), offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+
+        self.position = base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,102 @@
+use std::io::Write;
+
+use crate::error::Error;
+use crate::error::ensure_valid;
+use crate::format::ChunkId;
+use crate::format::FeatureFlags;
+use crate::format::IndexMagic;
+use crate::format::TABLE_TAIL_MARKER;
+use crate::index::Chunk;
+use crate::index::ChunkDigest;
+use crate::index::ChunkSize;
+
+const HEADER_SIZE: u64 = 48;
+const ITEM_SIZE: u64 = 8 + 32;
+
+/// Write an index, i.e. a `.caidx` or `.caibx`, as `read_index` reads them.
+///
+/// The header is written immediately, then each chunk as it's added;
+/// the index isn't valid until it's been `finish`ed.
+pub struct IndexWriter<W> {
+    inner: W,
+    /// the end of the last chunk
+    offset: u64,
+    chunks: u64,
+    /// from the feature flags, for `add`
+    digest: ChunkDigest,
+}
+
+impl<W: Write> IndexWriter<W> {
+    /// the `feature_flags` pick the digest for chunks which are `add`ed
+    pub fn new(
+        mut inner: W,
+        feature_flags: FeatureFlags,
+        sizes: ChunkSize,
+    ) -> Result<IndexWriter<W>, Error> {
+        for val in &[
+            HEADER_SIZE,
+            IndexMagic::Index.value(),
+            feature_flags.bits(),
+            sizes.min,
+            sizes.avg,
+            sizes.max,
+            // the table's size is unknown, as it's being streamed
+            u64::MAX,
+            IndexMagic::Table.value(),
+        ] {
+            inner.write_all(&val.to_le_bytes())?;
+        }
+
+        Ok(IndexWriter {
+            inner,
+            offset: 0,
+            chunks: 0,
+            digest: ChunkDigest::from_flags(feature_flags),
+        })
+    }
+
+    /// add the chunk which ends at `offset` in the stream
+    pub fn push(&mut self, offset: u64, id: &ChunkId) -> Result<(), Error> {
+        ensure_valid!(
+            offset > self.offset,
+            "chunks must be added in order, and not be empty: {} after {}",
+            offset,
+            self.offset
+        );
+
+        self.inner.write_all(&offset.to_le_bytes())?;
+        self.inner.write_all(id)?;
+        self.offset = offset;
+        self.chunks += 1;
+        Ok(())
+    }
+
+    /// add the next chunk of the stream, by its content, returning how it was recorded
+    pub fn add(&mut self, data: &[u8]) -> Result<Chunk, Error> {
+        let chunk = Chunk {
+            offset: self.offset + data.len() as u64,
+            id: self.digest.id(data),
+            digest: self.digest,
+        };
+        self.push(chunk.offset, &chunk.id)?;
+        Ok(chunk)
+    }
+
+    /// the length of the stream described so far
+    pub fn len(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        0 == self.offset
+    }
+
+    /// write the tail, and return the underlying writer, which has not been flushed
+    pub fn finish(mut self) -> Result<W, Error> {
+        let table_size = 16 + (self.chunks + 1) * ITEM_SIZE;
+        for val in &[0, 0, HEADER_SIZE, table_size, TABLE_TAIL_MARKER] {
+            self.inner.write_all(&val.to_le_bytes())?;
+        }
+        Ok(self.inner)
+    }
+}
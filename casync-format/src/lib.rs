@@ -1,18 +1,53 @@
+mod chunker;
 pub mod chunks;
+mod error;
 mod fetcher;
 mod flat;
 mod format;
+mod goodbye;
 mod index;
+mod index_reader;
+mod index_writer;
+mod packets;
+mod reader;
 mod stream;
+mod timestamp;
+mod writer;
 
+pub use crate::chunker::Chunker;
+pub use crate::chunker::Chunks;
+pub use crate::error::Error;
+pub use crate::fetcher::Fetcher;
 pub use crate::flat::FlatReader;
 pub use crate::format::ChunkId;
+pub use crate::format::FeatureFlags;
+pub use crate::format::StreamMagic;
+pub use crate::goodbye::Goodbye;
+pub use crate::goodbye::GoodbyeItem;
+pub use crate::goodbye::hash_name;
 pub use crate::index::Chunk;
+pub use crate::index::ChunkDigest;
+pub use crate::index::ChunkSize;
+pub use crate::index::IndexKind;
 pub use crate::index::format_chunk_id;
+pub use crate::index::parse_chunk_id;
 pub use crate::index::read_index;
+pub use crate::index_reader::IndexReader;
+pub use crate::index_writer::IndexWriter;
+pub use crate::packets::Packet;
+pub use crate::packets::PacketReader;
+pub use crate::reader::CatarReader;
+pub use crate::stream::ACL_EXECUTE;
+pub use crate::stream::ACL_READ;
+pub use crate::stream::ACL_WRITE;
+pub use crate::stream::Acl;
+pub use crate::stream::AclDefault;
+pub use crate::stream::AclEntry;
 pub use crate::stream::Content;
 pub use crate::stream::Entry;
 pub use crate::stream::Item;
 pub use crate::stream::Stream;
-pub use crate::stream::dump_packets;
+pub use crate::stream::Xattr;
 pub use crate::stream::utf8_path;
+pub use crate::timestamp::Timestamp;
+pub use crate::writer::CatarWriter;
@@ -0,0 +1,170 @@
+use std::io;
+use std::io::Read;
+
+use crate::error::Error;
+use crate::error::ensure;
+use crate::format::StreamMagic;
+use crate::stream::Entry;
+use crate::stream::GOODBYE_SIZE_LIMIT;
+use crate::stream::HEADER_TAG_LEN;
+use crate::stream::Header;
+use crate::stream::RECORD_SIZE_LIMIT;
+use crate::stream::leu64;
+use crate::stream::load_entry;
+use crate::stream::read_header;
+use crate::stream::read_record;
+
+/// One packet from a catar, as it appears in the file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    /// where the packet's header starts
+    pub offset: u64,
+    /// the length of the whole packet, including its header
+    pub size: u64,
+    pub magic: StreamMagic,
+    /// everything after the header, except for `Data`, whose content is skipped
+    pub payload: Vec<u8>,
+}
+
+/// Reads a catar packet by packet, without interpreting the structure,
+/// e.g. for looking at archives which `Stream` rejects.
+///
+/// Stops at the end of the input, or after the first error.
+pub struct PacketReader<R> {
+    inner: R,
+    offset: u64,
+    failed: bool,
+}
+
+impl Packet {
+    /// decode an `Entry` packet
+    pub fn entry(&self) -> Result<Entry, Error> {
+        ensure!(
+            StreamMagic::Entry == self.magic,
+            "expected an entry, not {:?}",
+            self.magic
+        );
+        load_entry(self.header(), &self.payload[..]).map_err(|e| e.at(self.offset))
+    }
+
+    /// the string in a `Name`, `Symlink`, `Hardlink`, `User`, `Group` or `Selinux` packet,
+    /// without its nul
+    pub fn text(&self) -> Option<&[u8]> {
+        match self.magic {
+            StreamMagic::Name
+            | StreamMagic::Symlink
+            | StreamMagic::Hardlink
+            | StreamMagic::User
+            | StreamMagic::Group
+            | StreamMagic::Selinux => {
+                Some(self.payload.strip_suffix(&[0]).unwrap_or(&self.payload[..]))
+            }
+            _ => None,
+        }
+    }
+
+    /// the `major` and `minor` numbers in a `Device` packet
+    pub fn device(&self) -> Option<(u64, u64)> {
+        if StreamMagic::Device != self.magic || 16 != self.payload.len() {
+            return None;
+        }
+        let major = leu64(&self.payload[..8]).ok()?;
+        let minor = leu64(&self.payload[8..]).ok()?;
+        Some((major, minor))
+    }
+
+    fn header(&self) -> Header {
+        Header {
+            offset: self.offset,
+            size: self.size,
+            magic: self.magic,
+        }
+    }
+}
+
+impl<R: Read> PacketReader<R> {
+    pub fn new(inner: R) -> PacketReader<R> {
+        PacketReader {
+            inner,
+            offset: 0,
+            failed: false,
+        }
+    }
+
+    /// where the next packet starts
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_packet(&mut self) -> Result<Option<Packet>, Error> {
+        let offset = self.offset;
+
+        // running out of input between packets is the normal end
+        let mut buf = [0u8; HEADER_TAG_LEN as usize];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.inner.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if io::ErrorKind::Interrupted == e.kind() => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if 0 == filled {
+            return Ok(None);
+        }
+        if filled < buf.len() {
+            return Err(Error::Truncated { offset, path: None });
+        }
+
+        let header = read_header(offset, &buf[..])?;
+        let payload = match header.magic {
+            StreamMagic::Data => {
+                ensure!(
+                    header.size >= HEADER_TAG_LEN,
+                    "data <0 bytes long: {}",
+                    header.size
+                );
+                let len = header.size - HEADER_TAG_LEN;
+                let skipped = io::copy(&mut (&mut self.inner).take(len), &mut io::sink())?;
+                if skipped != len {
+                    return Err(Error::Truncated { offset, path: None });
+                }
+                Vec::new()
+            }
+            StreamMagic::Bye => read_record(header, GOODBYE_SIZE_LIMIT, &mut self.inner)?,
+            _ => read_record(header, RECORD_SIZE_LIMIT, &mut self.inner)?,
+        };
+
+        self.offset += header.size;
+        Ok(Some(Packet {
+            offset,
+            size: header.size,
+            magic: header.magic,
+            payload,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for PacketReader<R> {
+    type Item = Result<Packet, Error>;
+
+    fn next(&mut self) -> Option<Result<Packet, Error>> {
+        if self.failed {
+            return None;
+        }
+
+        let offset = self.offset;
+        match self.read_packet() {
+            Ok(packet) => packet.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e.at(offset)))
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::error::Error;
+use crate::error::ensure;
+use crate::error::ensure_valid;
+use crate::format::StreamMagic;
+use crate::goodbye::Goodbye;
+use crate::goodbye::hash_name;
+use crate::stream::Content;
+use crate::stream::Entry;
+use crate::stream::GOODBYE_SIZE_LIMIT;
+use crate::stream::ItemType;
+use crate::stream::end_without_content;
+use crate::stream::leu64;
+use crate::stream::load_content;
+use crate::stream::load_entry;
+use crate::stream::load_metadata;
+use crate::stream::read_header;
+use crate::stream::read_record;
+use crate::stream::read_string_record;
+
+/// Random access to a catar, using the goodbye tables to find paths,
+/// instead of reading the whole thing like `Stream` does.
+pub struct CatarReader<R> {
+    inner: R,
+    root: Directory,
+}
+
+/// a goodbye table, and where it is in the file
+#[derive(Clone)]
+struct Directory {
+    start: u64,
+    table: Goodbye,
+}
+
+impl<R: Read + Seek> CatarReader<R> {
+    pub fn new(mut inner: R) -> Result<CatarReader<R>, Error> {
+        let end = inner.seek(SeekFrom::End(0))?;
+        let root = read_directory(&mut inner, end)?;
+        Ok(CatarReader { inner, root })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Find a `path`, like `etc/passwd`, relative to the root of the archive.
+    ///
+    /// An empty `path`, or `.`, is the root itself.
+    /// Files' `Content` reads the data directly from the underlying reader.
+    pub fn lookup<P: AsRef<[u8]>>(
+        &mut self,
+        path: P,
+    ) -> Result<Option<(Entry, Content<'_, R>)>, Error> {
+        let mut components = path
+            .as_ref()
+            .split(|&b| b'/' == b)
+            .filter(|name| !name.is_empty() && b"." != *name)
+            .peekable();
+
+        let mut dir = self.root.clone();
+
+        if components.peek().is_none() {
+            self.inner
+                .seek(SeekFrom::Start(dir.start - dir.table.entry_offset))?;
+        }
+
+        while let Some(name) = components.next() {
+            ensure_valid!(b".." != name, "'..' isn't supported in paths");
+
+            let (start, size) = match find_child(&mut self.inner, &dir, name)? {
+                Some(found) => found,
+                None => return Ok(None),
+            };
+
+            if components.peek().is_none() {
+                break;
+            }
+
+            let entry = read_entry(&mut self.inner)?;
+            ensure_valid!(
+                entry.is_dir(),
+                "{:?} is not a directory",
+                String::from_utf8_lossy(name)
+            );
+
+            let end = start.checked_add(size).ok_or_else(|| {
+                Error::malformed(format!("goodbye item size overflows: {}", size)).at(start)
+            })?;
+            dir = read_directory(&mut self.inner, end)?;
+        }
+
+        let (entry, item) = read_item(&mut self.inner)?;
+        let inner = &mut self.inner;
+        Ok(Some((
+            entry,
+            item.into_content(move |limit| inner.take(limit)),
+        )))
+    }
+}
+
+/// read the goodbye table of the directory which finishes at `end`
+fn read_directory<R: Read + Seek>(mut from: R, end: u64) -> Result<Directory, Error> {
+    ensure!(end >= 24, "too short to end with a goodbye table: {}", end);
+    from.seek(SeekFrom::Start(end - 24))?;
+    let _entry_offset = leu64(&mut from)?;
+    let size = leu64(&mut from)?;
+    ensure!(
+        size <= end,
+        "goodbye table is longer than the archive: {}",
+        size
+    );
+
+    let start = end - size;
+    from.seek(SeekFrom::Start(start))?;
+    let header = read_header(start, &mut from)?;
+    ensure!(
+        StreamMagic::Bye == header.magic,
+        "expected a goodbye table at {}, not {:?}",
+        start,
+        header.magic
+    );
+    ensure!(
+        size == header.size,
+        "goodbye table size mismatch at {}: {} != {}",
+        start,
+        size,
+        header.size
+    );
+
+    let table = Goodbye::parse(&read_record(header, GOODBYE_SIZE_LIMIT, &mut from)?)
+        .map_err(|e| e.at(start))?;
+    ensure!(
+        table.entry_offset <= start,
+        "goodbye table's entry is before the start of the archive"
+    );
+
+    Ok(Directory { start, table })
+}
+
+/// find the child called `name`, and leave `from` after its `Name` packet
+fn find_child<R: Read + Seek>(
+    mut from: R,
+    dir: &Directory,
+    name: &[u8],
+) -> Result<Option<(u64, u64)>, Error> {
+    for item in dir.table.find(hash_name(name)) {
+        ensure!(
+            item.offset <= dir.start,
+            "goodbye item points before the start of the archive"
+        );
+        let start = dir.start - item.offset;
+        from.seek(SeekFrom::Start(start))?;
+        let header = read_header(start, &mut from)?;
+        ensure!(
+            StreamMagic::Name == header.magic,
+            "goodbye item points at {:?}, not a name, at {}",
+            header.magic,
+            start
+        );
+
+        // not a collision
+        if name == read_string_record(header, &mut from)?.as_slice() {
+            return Ok(Some((start, item.size)));
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_entry<R: Read + Seek>(mut from: R) -> Result<Entry, Error> {
+    let header = read_header(from.stream_position()?, &mut from)?;
+    ensure!(
+        StreamMagic::Entry == header.magic,
+        "expected an entry, not {:?}",
+        header.magic
+    );
+    load_entry(header, &mut from).map_err(|e| e.at(header.offset))
+}
+
+/// read an item's `Entry`, its metadata, and the start of its content
+fn read_item<R: Read + Seek>(mut from: R) -> Result<(Entry, ItemType), Error> {
+    let mut entry = read_entry(&mut from)?;
+
+    loop {
+        let header = read_header(from.stream_position()?, &mut from)?;
+        match header.magic {
+            StreamMagic::Data
+            | StreamMagic::Symlink
+            | StreamMagic::Device
+            | StreamMagic::Hardlink => {
+                let item =
+                    load_content(header, &mut from, &entry).map_err(|e| e.at(header.offset))?;
+                return Ok((entry, item));
+            }
+            StreamMagic::Name | StreamMagic::Bye => {
+                if entry.is_dir() {
+                    return Ok((entry, ItemType::Directory));
+                }
+                match end_without_content(&entry) {
+                    Some(item) => return Ok((entry, item)),
+                    None => {
+                        return Err(
+                            Error::malformed("item ended without any content").at(header.offset)
+                        );
+                    }
+                }
+            }
+            StreamMagic::Entry => {
+                return Err(Error::malformed("entry found without data").at(header.offset));
+            }
+            _ => load_metadata(header, &mut from, &mut entry).map_err(|e| e.at(header.offset))?,
+        }
+    }
+}
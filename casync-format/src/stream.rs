@@ -1,21 +1,57 @@
-use std;
-use std::convert::TryFrom;
 use std::fmt;
 use std::io;
 use std::io::Read;
 
-use anyhow::Error;
-use anyhow::anyhow;
-use anyhow::ensure;
-
+use super::error::Error;
+use super::error::bail;
+use super::error::ensure;
+use super::format::FeatureFlags;
 use super::format::StreamMagic;
-
-const HEADER_TAG_LEN: u64 = 16;
-const RECORD_SIZE_LIMIT: u64 = 64 * 1024;
+use super::goodbye::Goodbye;
+use super::goodbye::GoodbyeItem;
+use super::goodbye::hash_name;
+use super::timestamp::Timestamp;
+
+pub(crate) const HEADER_TAG_LEN: u64 = 16;
+/// upstream's only layout: the header, then feature flags, mode, flags, uid, gid and mtime
+pub(crate) const ENTRY_SIZE: u64 = 8 * 6 + HEADER_TAG_LEN;
+pub(crate) const RECORD_SIZE_LIMIT: u64 = 64 * 1024;
+/// a directory with a million children
+pub(crate) const GOODBYE_SIZE_LIMIT: u64 = 24 * 1024 * 1024;
 
 pub struct Stream<R: Read> {
     inner: R,
     path: Path,
+    pending: Option<Header>,
+    /// where the next packet starts, assuming the caller reads every file to the end
+    offset: u64,
+    /// a `Frame` for each item in the `path`
+    frames: Vec<Frame>,
+}
+
+/// a packet header which has been read, but not yet processed
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Header {
+    pub(crate) offset: u64,
+    pub(crate) size: u64,
+    pub(crate) magic: StreamMagic,
+}
+
+/// where an item's packets are, so we can check the goodbye tables
+struct Frame {
+    /// the position of the `Name` packet
+    start: u64,
+    /// the position of the `Entry` packet
+    entry: u64,
+    hash: u64,
+    /// for directories, the items we've seen so far, with their absolute `offset`s
+    children: Vec<GoodbyeItem>,
+}
+
+/// keeps track of how far through the stream we are
+pub(crate) struct Tracked<'r, R> {
+    pub(crate) inner: &'r mut R,
+    pub(crate) offset: &'r mut u64,
 }
 
 #[derive(Debug, Clone)]
@@ -29,16 +65,65 @@ pub struct Item {
     pub entry: Option<Entry>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Entry {
     pub mode: u64,
     pub uid: u64,
     pub gid: u64,
-    pub mtime: u64,
-    pub feature_flags: u64,
+    pub mtime: Timestamp,
+    pub feature_flags: FeatureFlags,
     pub flags: u64,
     pub user_name: Option<Box<[u8]>>,
     pub group_name: Option<Box<[u8]>>,
+    pub xattrs: Vec<Xattr>,
+    pub acl: Acl,
+    pub selinux: Option<Box<[u8]>>,
+    /// the raw `security.capability` blob
+    pub fcaps: Option<Box<[u8]>>,
+}
+
+/// `(name, value)`, e.g. `(b"user.comment", b"hello")`
+pub type Xattr = (Box<[u8]>, Box<[u8]>);
+
+/// POSIX ACLs, beyond those implied by the `mode`.
+///
+/// `permissions` are a combination of `ACL_READ`, `ACL_WRITE` and `ACL_EXECUTE`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Acl {
+    pub user: Vec<AclEntry>,
+    pub group: Vec<AclEntry>,
+    pub group_obj: Option<u64>,
+    pub default: Option<AclDefault>,
+    pub default_user: Vec<AclEntry>,
+    pub default_group: Vec<AclEntry>,
+}
+
+/// a named user or group in an ACL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AclEntry {
+    /// the uid or gid
+    pub id: u64,
+    pub permissions: u64,
+    pub name: Option<Box<[u8]>>,
+}
+
+/// the default ACL for a directory, applied to new children
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AclDefault {
+    pub user_obj_permissions: u64,
+    pub group_obj_permissions: u64,
+    pub other_permissions: u64,
+    pub mask_permissions: u64,
+}
+
+pub const ACL_READ: u64 = 4;
+pub const ACL_WRITE: u64 = 2;
+pub const ACL_EXECUTE: u64 = 1;
+
+impl Acl {
+    pub fn is_empty(&self) -> bool {
+        *self == Acl::default()
+    }
 }
 
 impl Item {
@@ -71,6 +156,16 @@ impl fmt::Debug for Entry {
                 "d"
             } else if self.is_reg() {
                 "r"
+            } else if self.is_lnk() {
+                "l"
+            } else if self.is_chr() {
+                "c"
+            } else if self.is_blk() {
+                "b"
+            } else if self.is_fifo() {
+                "p"
+            } else if self.is_sock() {
+                "s"
             } else {
                 " XXX"
             },
@@ -90,28 +185,72 @@ impl Entry {
     pub fn is_reg(&self) -> bool {
         0o100000 == (self.mode & 0o170000)
     }
+
+    pub fn is_lnk(&self) -> bool {
+        0o120000 == (self.mode & 0o170000)
+    }
+
+    pub fn is_chr(&self) -> bool {
+        0o020000 == (self.mode & 0o170000)
+    }
+
+    pub fn is_blk(&self) -> bool {
+        0o060000 == (self.mode & 0o170000)
+    }
+
+    pub fn is_fifo(&self) -> bool {
+        0o010000 == (self.mode & 0o170000)
+    }
+
+    pub fn is_sock(&self) -> bool {
+        0o140000 == (self.mode & 0o170000)
+    }
 }
 
 #[derive(Clone, Debug)]
-enum ItemType {
+pub(crate) enum ItemType {
     File(u64),
     Directory,
+    Symlink(Box<[u8]>),
+    Device { major: u64, minor: u64 },
+    Fifo,
+    Socket,
+    Hardlink(Box<[u8]>),
 }
 
 #[derive(Debug)]
 pub enum Content<'r, R: 'r> {
     File(io::Take<&'r mut R>),
     Directory,
+    /// the target of the link, which is not necessarily a valid path
+    Symlink(Box<[u8]>),
+    /// a character or block device; check the `Entry` to find out which
+    Device {
+        major: u64,
+        minor: u64,
+    },
+    Fifo,
+    Socket,
+    /// Another name for a regular file which appeared earlier in the archive:
+    /// its path from the root, like `usr/bin/python3`.
+    ///
+    /// The `Entry` is the same as the target's.
+    Hardlink(Box<[u8]>),
 }
 
 impl ItemType {
-    fn into_content<'r, R: 'r + Read, F>(self, take: F) -> Content<'r, R>
+    pub(crate) fn into_content<'r, R: 'r + Read, F>(self, take: F) -> Content<'r, R>
     where
         F: FnOnce(u64) -> io::Take<&'r mut R>,
     {
         match self {
             ItemType::File(len) => Content::File(take(len)),
             ItemType::Directory => Content::Directory,
+            ItemType::Symlink(target) => Content::Symlink(target),
+            ItemType::Device { major, minor } => Content::Device { major, minor },
+            ItemType::Fifo => Content::Fifo,
+            ItemType::Socket => Content::Socket,
+            ItemType::Hardlink(target) => Content::Hardlink(target),
         }
     }
 }
@@ -121,6 +260,14 @@ impl<R: Read> Stream<R> {
         Stream {
             inner,
             path: Path::at_dot(),
+            pending: None,
+            offset: 0,
+            frames: vec![Frame {
+                start: 0,
+                entry: 0,
+                hash: 0,
+                children: Vec::new(),
+            }],
         }
     }
 
@@ -128,19 +275,55 @@ impl<R: Read> Stream<R> {
         self.inner
     }
 
+    /// How far through the stream we are, i.e. where the next packet starts,
+    /// assuming the content of every file has been read to the end.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Result<Option<(Path, Content<'_, R>)>, Error> {
         if self.path.is_empty() {
             return Ok(None);
         }
 
-        process_item(&mut self.inner, &mut self.path).map(move |item| {
-            let copy = self.path.clone();
-            self.path.pop();
-            Some((
-                copy,
-                item.into_content(move |limit| (&mut self.inner).take(limit)),
-            ))
-        })
+        let item = process_item(
+            &mut Tracked {
+                inner: &mut self.inner,
+                offset: &mut self.offset,
+            },
+            &mut self.path,
+            &mut self.frames,
+            &mut self.pending,
+        )
+        .map_err(|e| e.in_path(self.path.to_string()))?;
+
+        let end = match item {
+            ItemType::File(len) => self.offset + len,
+            ItemType::Fifo | ItemType::Socket => {
+                self.pending.expect("ended by a stashed header").offset
+            }
+            _ => self.offset,
+        };
+
+        let frame = self.frames.pop().expect("a frame for each path item");
+        if let Some(parent) = self.frames.last_mut() {
+            parent.children.push(GoodbyeItem {
+                hash: frame.hash,
+                offset: frame.start,
+                size: end - frame.start,
+            });
+        }
+        if let ItemType::File(len) = item {
+            self.offset += len;
+        }
+
+        let copy = self.path.clone();
+        self.path.pop();
+        Ok(Some((
+            copy,
+            item.into_content(move |limit| (&mut self.inner).take(limit)),
+        )))
     }
 }
 
@@ -156,6 +339,27 @@ impl<R: Read> AsMut<R> for Stream<R> {
     }
 }
 
+impl<R: Read> Read for Tracked<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        *self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+impl fmt::Display for Path {
+    /// like `./etc/passwd`, with any invalid utf-8 replaced
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, item) in self.inner.iter().enumerate() {
+            if 0 != i {
+                f.write_str("/")?;
+            }
+            f.write_str(&String::from_utf8_lossy(&item.name))?;
+        }
+        Ok(())
+    }
+}
+
 impl Path {
     fn at_dot() -> Path {
         Path {
@@ -190,126 +394,333 @@ impl Path {
     }
 }
 
-fn process_item<R: Read>(mut from: &mut R, path: &mut Path) -> Result<ItemType, Error> {
+fn process_item<R: Read>(
+    from: &mut Tracked<R>,
+    path: &mut Path,
+    frames: &mut Vec<Frame>,
+    pending: &mut Option<Header>,
+) -> Result<ItemType, Error> {
     loop {
-        let header_size = leu64(&mut from)?;
-        let header_format = StreamMagic::from(leu64(&mut from)?)?;
+        let header = match pending.take() {
+            Some(header) => header,
+            None => {
+                let offset = *from.offset;
+                read_header(offset, &mut *from).map_err(|e| e.at(offset))?
+            }
+        };
 
-        match header_format {
-            StreamMagic::Entry => {
-                ensure!(
-                    (8 * 6) + HEADER_TAG_LEN == header_size,
-                    "incorrect ENTRY length; not supported by us: 48 != {}",
-                    header_size
-                );
+        if let Some(item) =
+            process_packet(header, from, path, frames, pending).map_err(|e| e.at(header.offset))?
+        {
+            return Ok(item);
+        }
+    }
+}
 
-                let end = path.end_entry();
+/// apply a packet to the current item, returning it if it's now complete
+fn process_packet<R: Read>(
+    header: Header,
+    mut from: &mut Tracked<R>,
+    path: &mut Path,
+    frames: &mut Vec<Frame>,
+    pending: &mut Option<Header>,
+) -> Result<Option<ItemType>, Error> {
+    // fifos and sockets have no packet of their own; they end when the next
+    // item starts, or their directory ends, so we leave that packet for later
+    if let StreamMagic::Name | StreamMagic::Bye = header.magic
+        && let Some(ended) = path.end_entry().as_ref().and_then(end_without_content)
+    {
+        *pending = Some(header);
+        return Ok(Some(ended));
+    }
 
-                ensure!(end.is_none(), "entry found without data");
-                *end = Some(load_entry(&mut from)?);
-            }
-            StreamMagic::User => {
-                path.end_entry()
-                    .as_mut()
-                    .ok_or_else(|| anyhow!("user without entry"))?
-                    .user_name =
-                    Some(read_string_record(header_size, &mut from)?.into_boxed_slice());
-            }
-            StreamMagic::Group => {
-                path.end_entry()
-                    .as_mut()
-                    .ok_or_else(|| anyhow!("group without entry"))?
-                    .group_name =
-                    Some(read_string_record(header_size, &mut from)?.into_boxed_slice());
-            }
-            StreamMagic::Name => {
-                let new_name = read_string_record(header_size, &mut from)?;
+    match header.magic {
+        StreamMagic::Entry => {
+            let end = path.end_entry();
 
-                ensure!(!new_name.is_empty(), "filename must be non-empty");
+            ensure!(end.is_none(), "entry found without data");
+            *end = Some(load_entry(header, &mut from)?);
+            frames.last_mut().expect("frame per item").entry = header.offset;
+        }
+        StreamMagic::User
+        | StreamMagic::Group
+        | StreamMagic::Xattr
+        | StreamMagic::AclUser
+        | StreamMagic::AclGroup
+        | StreamMagic::AclGroupObj
+        | StreamMagic::AclDefault
+        | StreamMagic::AclDefaultUser
+        | StreamMagic::AclDefaultGroup
+        | StreamMagic::Fcaps
+        | StreamMagic::Selinux => {
+            let entry = path
+                .end_entry()
+                .as_mut()
+                .ok_or_else(|| Error::malformed(format!("{:?} without entry", header.magic)))?;
+            load_metadata(header, &mut from, entry)?;
+        }
+        StreamMagic::Data | StreamMagic::Symlink | StreamMagic::Device | StreamMagic::Hardlink => {
+            let entry = path
+                .end_entry()
+                .as_ref()
+                .ok_or_else(|| Error::malformed(format!("{:?} without entry", header.magic)))?;
+            return load_content(header, &mut from, entry).map(Some);
+        }
+        StreamMagic::Name => {
+            let new_name = read_string_record(header, &mut from)?;
+
+            ensure!(!new_name.is_empty(), "filename must be non-empty");
+
+            frames.push(Frame {
+                start: header.offset,
+                entry: header.offset,
+                hash: hash_name(&new_name),
+                children: Vec::new(),
+            });
+
+            path.push(Item {
+                name: new_name.into_boxed_slice(),
+                entry: None,
+            });
+        }
+        StreamMagic::Bye => {
+            ensure!(
+                path.end_entry().as_ref().is_some_and(|e| e.is_dir()),
+                "goodbye for non-directory"
+            );
+            let table = read_record(header, GOODBYE_SIZE_LIMIT, &mut from)?;
+            let frame = frames.last().expect("frame per item");
+            Goodbye::parse(&table)?.validate(
+                header.size,
+                header.offset,
+                frame.entry,
+                &frame.children,
+            )?;
+            return Ok(Some(ItemType::Directory));
+        }
+    }
 
-                path.push(Item {
-                    name: new_name.into_boxed_slice(),
-                    entry: None,
-                });
-            }
-            StreamMagic::Data => {
-                ensure!(
-                    header_size >= HEADER_TAG_LEN,
-                    "data <0 bytes long: {}",
-                    header_size
-                );
-                return Ok(ItemType::File(header_size - HEADER_TAG_LEN));
-            }
-            StreamMagic::Bye => {
-                // TODO: all kinds of tailing records
-                read_data_record(header_size, &mut from)?;
-                return Ok(ItemType::Directory);
-            }
+    Ok(None)
+}
+
+/// read the packet which ends an item, leaving any file data unread
+pub(crate) fn load_content<R: Read>(
+    header: Header,
+    mut from: R,
+    entry: &Entry,
+) -> Result<ItemType, Error> {
+    let header_size = header.size;
+    Ok(match header.magic {
+        StreamMagic::Data => {
+            ensure!(entry.is_reg(), "data for non-regular file");
+            ensure!(
+                header_size >= HEADER_TAG_LEN,
+                "data <0 bytes long: {}",
+                header_size
+            );
+            ItemType::File(header_size - HEADER_TAG_LEN)
         }
+        StreamMagic::Symlink => {
+            ensure!(entry.is_lnk(), "symlink target for non-symlink");
+            let target = read_string_record(header, &mut from)?;
+            ItemType::Symlink(target.into_boxed_slice())
+        }
+        StreamMagic::Device => {
+            ensure!(
+                entry.is_chr() || entry.is_blk(),
+                "device numbers for non-device"
+            );
+            ensure!(
+                8 * 2 + HEADER_TAG_LEN == header_size,
+                "incorrect DEVICE length: 32 != {}",
+                header_size
+            );
+            let major = leu64(&mut from)?;
+            let minor = leu64(&mut from)?;
+            ItemType::Device { major, minor }
+        }
+        StreamMagic::Hardlink => {
+            ensure!(entry.is_reg(), "hardlink for non-regular file");
+            let target = read_string_record(header, &mut from)?;
+            ensure!(
+                is_relative_path(&target),
+                "unsafe hardlink target: {:?}",
+                String::from_utf8_lossy(&target)
+            );
+            ItemType::Hardlink(target.into_boxed_slice())
+        }
+        other => bail!("not a content packet: {:?}", other),
+    })
+}
+
+/// apply a packet to the `Entry` it follows, e.g. a `User` or an `Xattr`
+pub(crate) fn load_metadata<R: Read>(
+    header: Header,
+    mut from: R,
+    entry: &mut Entry,
+) -> Result<(), Error> {
+    let header_size = header.size;
+    match header.magic {
+        StreamMagic::User => {
+            entry.user_name = Some(read_string_record(header, &mut from)?.into_boxed_slice());
+        }
+        StreamMagic::Group => {
+            entry.group_name = Some(read_string_record(header, &mut from)?.into_boxed_slice());
+        }
+        StreamMagic::Xattr => {
+            let mut record = read_data_record(header, &mut from)?;
+            let nul = record
+                .iter()
+                .position(|&b| 0 == b)
+                .ok_or_else(|| Error::malformed("xattr name must be null-terminated"))?;
+            ensure!(0 != nul, "xattr name must be non-empty");
+            let value = record.split_off(nul + 1);
+            record.pop();
+            entry
+                .xattrs
+                .push((record.into_boxed_slice(), value.into_boxed_slice()));
+        }
+        StreamMagic::AclUser => {
+            let acl_entry = read_acl_entry(header, &mut from)?;
+            entry.acl.user.push(acl_entry);
+        }
+        StreamMagic::AclGroup => {
+            let acl_entry = read_acl_entry(header, &mut from)?;
+            entry.acl.group.push(acl_entry);
+        }
+        StreamMagic::AclGroupObj => {
+            ensure!(
+                8 + HEADER_TAG_LEN == header_size,
+                "incorrect ACL_GROUP_OBJ length: 24 != {}",
+                header_size
+            );
+            let permissions = leu64(&mut from)?;
+            entry.acl.group_obj = Some(permissions);
+        }
+        StreamMagic::AclDefault => {
+            ensure!(
+                8 * 4 + HEADER_TAG_LEN == header_size,
+                "incorrect ACL_DEFAULT length: 48 != {}",
+                header_size
+            );
+            let default = AclDefault {
+                user_obj_permissions: leu64(&mut from)?,
+                group_obj_permissions: leu64(&mut from)?,
+                other_permissions: leu64(&mut from)?,
+                mask_permissions: leu64(&mut from)?,
+            };
+            entry.acl.default = Some(default);
+        }
+        StreamMagic::AclDefaultUser => {
+            let acl_entry = read_acl_entry(header, &mut from)?;
+            entry.acl.default_user.push(acl_entry);
+        }
+        StreamMagic::AclDefaultGroup => {
+            let acl_entry = read_acl_entry(header, &mut from)?;
+            entry.acl.default_group.push(acl_entry);
+        }
+        StreamMagic::Fcaps => {
+            let fcaps = read_data_record(header, &mut from)?;
+            entry.fcaps = Some(fcaps.into_boxed_slice());
+        }
+        StreamMagic::Selinux => {
+            let label = read_string_record(header, &mut from)?;
+            entry.selinux = Some(label.into_boxed_slice());
+        }
+        other => bail!("not a metadata packet: {:?}", other),
     }
+    Ok(())
 }
 
-fn load_entry<R: Read>(mut from: R) -> Result<Entry, Error> {
-    Ok(Entry {
-        feature_flags: leu64(&mut from)?,
+fn read_acl_entry<R: Read>(header: Header, mut from: R) -> Result<AclEntry, Error> {
+    ensure!(
+        header.size >= 8 * 2 + HEADER_TAG_LEN,
+        "acl entry too short: {}",
+        header.size
+    );
+    let id = leu64(&mut from)?;
+    let permissions = leu64(&mut from)?;
+    let rest = Header {
+        size: header.size - 8 * 2,
+        ..header
+    };
+    let name = read_string_record(rest, &mut from)?;
+    Ok(AclEntry {
+        id,
+        permissions,
+        name: if name.is_empty() {
+            None
+        } else {
+            Some(name.into_boxed_slice())
+        },
+    })
+}
+
+/// like `usr/bin`: no empty, `.` or `..` components, so it can't escape from the root
+pub(crate) fn is_relative_path(path: &[u8]) -> bool {
+    path.split(|&b| b'/' == b)
+        .all(|name| !name.is_empty() && b"." != name && b".." != name)
+}
+
+pub(crate) fn end_without_content(entry: &Entry) -> Option<ItemType> {
+    if entry.is_fifo() {
+        Some(ItemType::Fifo)
+    } else if entry.is_sock() {
+        Some(ItemType::Socket)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn read_header<R: Read>(offset: u64, mut from: R) -> Result<Header, Error> {
+    let size = leu64(&mut from)?;
+    let magic = leu64(&mut from)?;
+    Ok(Header {
+        offset,
+        size,
+        magic: StreamMagic::from(magic).ok_or(Error::UnknownMagic {
+            magic,
+            offset,
+            path: None,
+        })?,
+    })
+}
+
+pub(crate) fn load_entry<R: Read>(header: Header, mut from: R) -> Result<Entry, Error> {
+    ensure!(
+        ENTRY_SIZE == header.size,
+        "unsupported ENTRY size: {} bytes; upstream's are {} (feature flags, mode, flags, uid, gid and mtime)",
+        header.size,
+        ENTRY_SIZE
+    );
+    let entry = Entry {
+        feature_flags: FeatureFlags::from_raw(leu64(&mut from)?)?,
         mode: leu64(&mut from)?,
         flags: leu64(&mut from)?,
         uid: leu64(&mut from)?,
         gid: leu64(&mut from)?,
-        mtime: leu64(&mut from)?,
+        mtime: Timestamp::from_nanos(leu64(&mut from)?),
         // these are filled in by following packets
         user_name: None,
         group_name: None,
-    })
-}
-
-pub fn dump_packets<R: Read>(mut from: R) -> Result<(), Error> {
-    let mut depth = 0usize;
-    loop {
-        let header_size = leu64(&mut from)?;
-        let header_format = StreamMagic::from(leu64(&mut from)?)?;
-
-        let payload_len = header_size - 16;
-        let mut payload = vec![0; usize::try_from(payload_len)?];
-        from.read_exact(&mut payload)?;
-        print!(
-            "{} * {:5} | {:3} | ",
-            String::from_utf8(vec![b' '; depth * 2]).unwrap(),
-            format!("{:?}", header_format),
-            payload_len
+        xattrs: Vec::new(),
+        acl: Acl::default(),
+        selinux: None,
+        fcaps: None,
+    };
+    if let Some(granularity) = Timestamp::granularity(entry.feature_flags) {
+        ensure!(
+            entry.mtime.truncate(granularity) == entry.mtime,
+            "mtime {} is more precise than the entry's feature flags allow: {:?}",
+            entry.mtime,
+            granularity
         );
-
-        match header_format {
-            StreamMagic::Entry => {
-                let entry = load_entry(io::Cursor::new(&payload))?;
-                println!("dir: {}", entry.is_dir());
-            }
-            StreamMagic::Data => {
-                println!();
-
-                depth -= 1;
-            }
-            StreamMagic::Name => {
-                println!("{}", String::from_utf8_lossy(&payload[..payload.len() - 1]));
-                depth += 1;
-            }
-            StreamMagic::Bye => {
-                println!();
-                depth -= 1;
-
-                if 0 == depth {
-                    return Ok(());
-                }
-            }
-            _ => {
-                println!("{}", String::from_utf8_lossy(&payload[..payload.len() - 1]));
-            }
-        }
     }
+    Ok(entry)
 }
 
-fn read_string_record<R: Read>(header_size: u64, from: R) -> Result<Vec<u8>, Error> {
-    match read_data_record(header_size, from) {
+pub(crate) fn read_string_record<R: Read>(header: Header, from: R) -> Result<Vec<u8>, Error> {
+    match read_data_record(header, from) {
         Ok(ref vec) if vec.is_empty() => Ok(Vec::new()),
         Ok(mut vec) => {
             ensure!(
@@ -323,20 +734,31 @@ fn read_string_record<R: Read>(header_size: u64, from: R) -> Result<Vec<u8>, Err
     }
 }
 
-fn read_data_record<R: Read>(header_size: u64, mut from: R) -> Result<Vec<u8>, Error> {
+fn read_data_record<R: Read>(header: Header, from: R) -> Result<Vec<u8>, Error> {
+    read_record(header, RECORD_SIZE_LIMIT, from)
+}
+
+pub(crate) fn read_record<R: Read>(
+    header: Header,
+    limit: u64,
+    mut from: R,
+) -> Result<Vec<u8>, Error> {
     ensure!(
-        header_size >= HEADER_TAG_LEN,
+        header.size >= HEADER_TAG_LEN,
         "header missing / size wrong: {}",
-        header_size
+        header.size
     );
 
-    ensure!(
-        header_size < RECORD_SIZE_LIMIT + HEADER_TAG_LEN,
-        "refusing to support records over ~64kB, was: {}",
-        header_size
-    );
+    if header.size >= limit + HEADER_TAG_LEN {
+        return Err(Error::RecordTooLarge {
+            size: header.size,
+            limit,
+            offset: header.offset,
+            path: None,
+        });
+    }
 
-    let mut buf = vec![0u8; (header_size - HEADER_TAG_LEN) as usize];
+    let mut buf = vec![0u8; (header.size - HEADER_TAG_LEN) as usize];
     from.read_exact(&mut buf)?;
     Ok(buf)
 }
@@ -347,7 +769,7 @@ pub fn utf8_path(
     let mut ret = String::new();
     for component in from {
         ret.push_str(String::from_utf8(component.into_vec())?.as_str());
-        ret.push_str("/");
+        ret.push('/');
     }
 
     if !ret.is_empty() {
@@ -358,7 +780,7 @@ pub fn utf8_path(
     Ok(ret)
 }
 
-fn leu64<R: Read>(mut from: R) -> io::Result<u64> {
+pub(crate) fn leu64<R: Read>(mut from: R) -> io::Result<u64> {
     let mut buf = [0u8; 8];
     from.read_exact(&mut buf)?;
     Ok(u64::from_le_bytes(buf))
@@ -0,0 +1,85 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::error::Error;
+use crate::format::FeatureFlags;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// A modification time, as upstream stores it: nanoseconds since the unix epoch.
+///
+/// This covers 1970 to 2554; earlier times can't be represented in the format.
+/// How precise it really is depends on the entry's feature flags, see `granularity`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    nanos: u64,
+}
+
+impl Timestamp {
+    pub const fn from_nanos(nanos: u64) -> Timestamp {
+        Timestamp { nanos }
+    }
+
+    /// nanoseconds since the epoch, as written in an `Entry`
+    pub const fn as_nanos(self) -> u64 {
+        self.nanos
+    }
+
+    /// whole seconds since the epoch
+    pub const fn secs(self) -> u64 {
+        self.nanos / NANOS_PER_SEC
+    }
+
+    pub const fn subsec_nanos(self) -> u32 {
+        (self.nanos % NANOS_PER_SEC) as u32
+    }
+
+    /// rounded down to a multiple of `granularity`, as upstream does when writing
+    pub fn truncate(self, granularity: Duration) -> Timestamp {
+        let step = granularity.as_nanos().clamp(1, u128::from(u64::MAX)) as u64;
+        Timestamp::from_nanos(self.nanos - self.nanos % step)
+    }
+
+    /// as the `WITH_*_TIME` feature flags say, or `None` if times aren't recorded
+    pub fn granularity(flags: FeatureFlags) -> Option<Duration> {
+        if flags.contains(FeatureFlags::WITH_NSEC_TIME) {
+            Some(Duration::from_nanos(1))
+        } else if flags.contains(FeatureFlags::WITH_USEC_TIME) {
+            Some(Duration::from_micros(1))
+        } else if flags.contains(FeatureFlags::WITH_SEC_TIME) {
+            Some(Duration::from_secs(1))
+        } else if flags.contains(FeatureFlags::WITH_2SEC_TIME) {
+            Some(Duration::from_secs(2))
+        } else {
+            None
+        }
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(time: Timestamp) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_nanos(time.nanos)
+    }
+}
+
+impl TryFrom<SystemTime> for Timestamp {
+    type Error = Error;
+
+    /// fails for times before 1970, or after 2554
+    fn try_from(time: SystemTime) -> Result<Timestamp, Error> {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .ok()
+            .and_then(|since| u64::try_from(since.as_nanos()).ok())
+            .map(Timestamp::from_nanos)
+            .ok_or_else(|| Error::Invalid(format!("unsupported timestamp: {:?}", time)))
+    }
+}
+
+impl fmt::Display for Timestamp {
+    /// like `1500000000.123456789`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:09}", self.secs(), self.subsec_nanos())
+    }
+}
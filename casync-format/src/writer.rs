@@ -0,0 +1,347 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use crate::error::Error;
+use crate::error::ensure_valid;
+use crate::format::StreamMagic;
+use crate::goodbye::Goodbye;
+use crate::goodbye::GoodbyeItem;
+use crate::goodbye::hash_name;
+use crate::stream::AclEntry;
+use crate::stream::Entry;
+use crate::stream::is_relative_path;
+use crate::timestamp::Timestamp;
+
+/// Serialise a directory tree into a `catar`, from any source.
+///
+/// Children must be added in byte order of their names, as upstream does, and each
+/// `begin_dir` needs a matching `end_dir`. Nothing is valid until it's been `finish`ed.
+pub struct CatarWriter<W> {
+    inner: W,
+    /// how much has been written, for the goodbye tables
+    offset: u64,
+    /// the directories which have been begun, but not ended; the root first
+    dirs: Vec<OpenDir>,
+}
+
+struct OpenDir {
+    entry_start: u64,
+    /// where this directory's own `Name` is, in its parent, and its hash
+    name: Option<(u64, u64)>,
+    last_child: Option<Box<[u8]>>,
+    children: Vec<GoodbyeItem>,
+}
+
+impl<W: Write> CatarWriter<W> {
+    /// start an archive, with the `entry` for its root directory
+    pub fn new(inner: W, root: &Entry) -> Result<CatarWriter<W>, Error> {
+        ensure_valid!(root.is_dir(), "the root of an archive must be a directory");
+        let mut writer = CatarWriter {
+            inner,
+            offset: 0,
+            dirs: Vec::new(),
+        };
+        writer.entry(root)?;
+        writer.dirs.push(OpenDir {
+            entry_start: 0,
+            name: None,
+            last_child: None,
+            children: Vec::new(),
+        });
+        Ok(writer)
+    }
+
+    /// start a directory; everything added until the matching `end_dir` is inside it
+    pub fn begin_dir(&mut self, name: &[u8], entry: &Entry) -> Result<(), Error> {
+        ensure_valid!(entry.is_dir(), "begin_dir needs a directory entry");
+        let name_start = self.name(name)?;
+        let entry_start = self.offset;
+        self.entry(entry)?;
+        self.dirs.push(OpenDir {
+            entry_start,
+            name: Some((name_start, hash_name(name))),
+            last_child: None,
+            children: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// finish the directory from the last `begin_dir`
+    pub fn end_dir(&mut self) -> Result<(), Error> {
+        ensure_valid!(self.dirs.len() > 1, "end_dir without begin_dir");
+        let dir = self.dirs.pop().expect("checked");
+        let (name_start, hash) = dir.name.expect("only the root has no name");
+        self.goodbye(dir.entry_start, dir.children)?;
+        self.end_item(name_start, hash);
+        Ok(())
+    }
+
+    /// add a regular file, with all of the content of `reader`, which is read into memory
+    pub fn add_file<R: Read>(
+        &mut self,
+        name: &[u8],
+        entry: &Entry,
+        mut reader: R,
+    ) -> Result<(), Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        self.add_file_with_len(name, entry, data.len() as u64, io::Cursor::new(data))
+    }
+
+    /// add a regular file, streaming `len` bytes from `reader`, which must have that many
+    pub fn add_file_with_len<R: Read>(
+        &mut self,
+        name: &[u8],
+        entry: &Entry,
+        len: u64,
+        reader: R,
+    ) -> Result<(), Error> {
+        ensure_valid!(entry.is_reg(), "add_file needs a regular file entry");
+        let name_start = self.name(name)?;
+        self.entry(entry)?;
+        self.header(StreamMagic::Data, len)?;
+        let copied = io::copy(&mut reader.take(len), &mut self.inner)?;
+        ensure_valid!(len == copied, "file was {} bytes, not {}", copied, len);
+        self.offset += len;
+        self.end_item(name_start, hash_name(name));
+        Ok(())
+    }
+
+    pub fn add_symlink(&mut self, name: &[u8], entry: &Entry, target: &[u8]) -> Result<(), Error> {
+        ensure_valid!(entry.is_lnk(), "add_symlink needs a symlink entry");
+        let name_start = self.name(name)?;
+        self.entry(entry)?;
+        self.string(StreamMagic::Symlink, target)?;
+        self.end_item(name_start, hash_name(name));
+        Ok(())
+    }
+
+    /// Add another name for a regular file which has already been added.
+    ///
+    /// `target` is its path from the root, like `usr/bin/python3`.
+    /// Upstream can't read archives with hardlinks in.
+    pub fn add_hardlink(&mut self, name: &[u8], entry: &Entry, target: &[u8]) -> Result<(), Error> {
+        ensure_valid!(entry.is_reg(), "add_hardlink needs a regular file entry");
+        ensure_valid!(
+            is_relative_path(target),
+            "invalid hardlink target: {:?}",
+            String::from_utf8_lossy(target)
+        );
+        let name_start = self.name(name)?;
+        self.entry(entry)?;
+        self.string(StreamMagic::Hardlink, target)?;
+        self.end_item(name_start, hash_name(name));
+        Ok(())
+    }
+
+    /// add a character or block device, as told by the `entry`
+    pub fn add_device(
+        &mut self,
+        name: &[u8],
+        entry: &Entry,
+        major: u64,
+        minor: u64,
+    ) -> Result<(), Error> {
+        ensure_valid!(
+            entry.is_chr() || entry.is_blk(),
+            "add_device needs a device entry"
+        );
+        let name_start = self.name(name)?;
+        self.entry(entry)?;
+        let mut payload = Vec::with_capacity(8 * 2);
+        payload.extend_from_slice(&major.to_le_bytes());
+        payload.extend_from_slice(&minor.to_le_bytes());
+        self.packet(StreamMagic::Device, &payload)?;
+        self.end_item(name_start, hash_name(name));
+        Ok(())
+    }
+
+    /// add a fifo or a socket, which have no content
+    pub fn add_special(&mut self, name: &[u8], entry: &Entry) -> Result<(), Error> {
+        ensure_valid!(
+            entry.is_fifo() || entry.is_sock(),
+            "add_special needs a fifo or socket entry"
+        );
+        let name_start = self.name(name)?;
+        self.entry(entry)?;
+        self.end_item(name_start, hash_name(name));
+        Ok(())
+    }
+
+    /// end the root directory, and return the underlying writer, which hasn't been flushed
+    pub fn finish(mut self) -> Result<W, Error> {
+        ensure_valid!(
+            1 == self.dirs.len(),
+            "{} directories were not ended",
+            self.dirs.len() - 1
+        );
+        let root = self.dirs.pop().expect("checked");
+        self.goodbye(root.entry_start, root.children)?;
+        Ok(self.inner)
+    }
+
+    /// write the `Name` of a new child of the current directory, returning where it started
+    fn name(&mut self, name: &[u8]) -> Result<u64, Error> {
+        ensure_valid!(
+            !name.is_empty()
+                && b"." != name
+                && b".." != name
+                && !name.contains(&b'/')
+                && !name.contains(&0),
+            "invalid name: {:?}",
+            String::from_utf8_lossy(name)
+        );
+
+        let dir = self.dirs.last_mut().expect("the root is open until finish");
+        if let Some(last) = &dir.last_child
+            && name <= &last[..]
+        {
+            return Err(Error::Invalid(format!(
+                "names must be added in order, without duplicates: {:?} after {:?}",
+                String::from_utf8_lossy(name),
+                String::from_utf8_lossy(last)
+            )));
+        }
+        dir.last_child = Some(name.into());
+
+        let start = self.offset;
+        self.string(StreamMagic::Name, name)?;
+        Ok(start)
+    }
+
+    fn end_item(&mut self, name_start: u64, hash: u64) {
+        let size = self.offset - name_start;
+        self.dirs
+            .last_mut()
+            .expect("the root is open until finish")
+            .children
+            .push(GoodbyeItem {
+                hash,
+                offset: name_start,
+                size,
+            });
+    }
+
+    fn goodbye(&mut self, entry_start: u64, mut children: Vec<GoodbyeItem>) -> Result<(), Error> {
+        let start = self.offset;
+        for child in &mut children {
+            child.offset = start - child.offset;
+        }
+        let table = Goodbye::from_items(children, start - entry_start);
+        self.packet(StreamMagic::Bye, &table.to_payload())
+    }
+
+    /// the `Entry`, and all the metadata packets which follow it
+    fn entry(&mut self, entry: &Entry) -> Result<(), Error> {
+        if let Some(granularity) = Timestamp::granularity(entry.feature_flags) {
+            ensure_valid!(
+                entry.mtime.truncate(granularity) == entry.mtime,
+                "mtime {} is more precise than the feature flags allow: {:?}",
+                entry.mtime,
+                granularity
+            );
+        }
+
+        let mut payload = Vec::with_capacity(8 * 6);
+        for val in &[
+            entry.feature_flags.bits(),
+            entry.mode,
+            entry.flags,
+            entry.uid,
+            entry.gid,
+            entry.mtime.as_nanos(),
+        ] {
+            payload.extend_from_slice(&val.to_le_bytes());
+        }
+        self.packet(StreamMagic::Entry, &payload)?;
+
+        if let Some(name) = &entry.user_name {
+            self.string(StreamMagic::User, name)?;
+        }
+        if let Some(name) = &entry.group_name {
+            self.string(StreamMagic::Group, name)?;
+        }
+
+        for (name, value) in &entry.xattrs {
+            ensure_valid!(
+                !name.is_empty() && !name.contains(&0),
+                "invalid xattr name: {:?}",
+                String::from_utf8_lossy(name)
+            );
+            self.packet(StreamMagic::Xattr, &[name, &b"\0"[..], value].concat())?;
+        }
+
+        let acl = &entry.acl;
+        for user in &acl.user {
+            self.acl_entry(StreamMagic::AclUser, user)?;
+        }
+        for group in &acl.group {
+            self.acl_entry(StreamMagic::AclGroup, group)?;
+        }
+        if let Some(permissions) = acl.group_obj {
+            self.packet(StreamMagic::AclGroupObj, &permissions.to_le_bytes())?;
+        }
+        if let Some(default) = &acl.default {
+            let mut payload = Vec::with_capacity(8 * 4);
+            for val in &[
+                default.user_obj_permissions,
+                default.group_obj_permissions,
+                default.other_permissions,
+                default.mask_permissions,
+            ] {
+                payload.extend_from_slice(&val.to_le_bytes());
+            }
+            self.packet(StreamMagic::AclDefault, &payload)?;
+        }
+        for user in &acl.default_user {
+            self.acl_entry(StreamMagic::AclDefaultUser, user)?;
+        }
+        for group in &acl.default_group {
+            self.acl_entry(StreamMagic::AclDefaultGroup, group)?;
+        }
+
+        if let Some(fcaps) = &entry.fcaps {
+            self.packet(StreamMagic::Fcaps, fcaps)?;
+        }
+        if let Some(label) = &entry.selinux {
+            self.string(StreamMagic::Selinux, label)?;
+        }
+
+        Ok(())
+    }
+
+    fn acl_entry(&mut self, magic: StreamMagic, acl_entry: &AclEntry) -> Result<(), Error> {
+        let mut payload = Vec::with_capacity(8 * 2 + 1);
+        payload.extend_from_slice(&acl_entry.id.to_le_bytes());
+        payload.extend_from_slice(&acl_entry.permissions.to_le_bytes());
+        if let Some(name) = &acl_entry.name {
+            payload.extend_from_slice(name);
+        }
+        payload.push(0);
+        self.packet(magic, &payload)
+    }
+
+    /// a packet containing a null-terminated string
+    fn string(&mut self, magic: StreamMagic, value: &[u8]) -> Result<(), Error> {
+        ensure_valid!(!value.contains(&0), "{:?} can't contain a null", magic);
+        let mut payload = Vec::with_capacity(value.len() + 1);
+        payload.extend_from_slice(value);
+        payload.push(0);
+        self.packet(magic, &payload)
+    }
+
+    fn packet(&mut self, magic: StreamMagic, payload: &[u8]) -> Result<(), Error> {
+        self.header(magic, payload.len() as u64)?;
+        self.inner.write_all(payload)?;
+        self.offset += payload.len() as u64;
+        Ok(())
+    }
+
+    fn header(&mut self, magic: StreamMagic, payload_len: u64) -> Result<(), Error> {
+        self.inner.write_all(&(16 + payload_len).to_le_bytes())?;
+        self.inner.write_all(&magic.value().to_le_bytes())?;
+        self.offset += 16;
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+
+use anyhow::Error;
+
+use casync_format::ChunkSize;
+use casync_format::Chunker;
+use casync_format::Chunks;
+use casync_format::chunks::from_index;
+use casync_format::read_index;
+
+fn nums_stream() -> Result<Vec<u8>, Error> {
+    let mut stream = Vec::new();
+    from_index("tests/data/nums.caidx", |path: &str| fs::read(path))?.read_to_end(&mut stream)?;
+    Ok(stream)
+}
+
+/// the same cuts as upstream made when writing the index
+#[test]
+fn matches_upstream() -> Result<(), Error> {
+    let stream = nums_stream()?;
+    let (sizes, expected) = read_index(fs::File::open("tests/data/nums.caidx")?)?;
+
+    let chunks = Chunks::new(io::Cursor::new(&stream), sizes)
+        .collect::<Result<Vec<Vec<u8>>, io::Error>>()?;
+
+    assert_eq!(expected.len(), chunks.len());
+    let mut end = 0;
+    for (chunk, expected) in chunks.iter().zip(expected.iter()) {
+        end += chunk.len() as u64;
+        assert_eq!(expected.offset, end);
+        expected.check(chunk)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn split_input() -> Result<(), Error> {
+    let stream = nums_stream()?;
+    let (sizes, expected) = read_index(fs::File::open("tests/data/nums.caidx")?)?;
+
+    // a cut might be found inside the first window, or in any piece
+    for piece in [1, 7, 47, 48, 49, 1000] {
+        let mut chunker = Chunker::new(sizes);
+        let mut cuts = Vec::new();
+        let mut chunk_start = 0;
+        for (i, mut buf) in stream.chunks(piece).enumerate() {
+            let mut pos = i * piece;
+            while let Some(used) = chunker.scan(buf) {
+                pos += used;
+                cuts.push(pos as u64);
+                chunk_start = pos;
+                buf = &buf[used..];
+            }
+        }
+        if chunk_start != stream.len() {
+            cuts.push(stream.len() as u64);
+        }
+
+        let expected: Vec<u64> = expected.iter().map(|chunk| chunk.offset).collect();
+        assert_eq!(expected, cuts, "pieces of {}", piece);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn limits() -> Result<(), Error> {
+    let sizes = ChunkSize::new(64, 256, 1024)?;
+
+    // xorshift, so the data is varied, but repeatable
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let data: Vec<u8> = (0..100_000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+
+    let chunks =
+        Chunks::new(io::Cursor::new(&data), sizes).collect::<Result<Vec<Vec<u8>>, io::Error>>()?;
+
+    assert_eq!(data, chunks.concat());
+    let (last, rest) = chunks.split_last().unwrap();
+    assert!(!last.is_empty() && last.len() <= 1024);
+    for chunk in rest {
+        assert!(chunk.len() >= 64 && chunk.len() <= 1024, "{}", chunk.len());
+    }
+
+    // the average is vaguely right, and not just the limits
+    assert!(chunks.len() > 100_000 / 1024);
+    assert!(chunks.iter().any(|chunk| chunk.len() < 1024));
+
+    // nothing to cut
+    assert_eq!(0, Chunks::new(io::empty(), sizes).count());
+    Ok(())
+}
+
+/// averages so small that the chunker could never cut are refused, rather than dividing by zero
+#[test]
+fn tiny_average() -> Result<(), Error> {
+    assert!(matches!(
+        ChunkSize::new(1, 1, 100),
+        Err(casync_format::Error::Invalid(_))
+    ));
+    assert!(ChunkSize::from_avg(1).is_err());
+
+    // the smallest which works
+    let sizes = ChunkSize::new(1, 2, 100)?;
+    let data = vec![7u8; 1000];
+    let chunks =
+        Chunks::new(io::Cursor::new(&data), sizes).collect::<Result<Vec<Vec<u8>>, io::Error>>()?;
+    assert_eq!(data, chunks.concat());
+    Ok(())
+}
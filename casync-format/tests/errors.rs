@@ -0,0 +1,180 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+
+use casync_format::Error;
+use casync_format::Stream;
+use casync_format::chunks::from_index;
+use casync_format::read_index;
+
+fn read_all<R: Read>(mut stream: Stream<R>) -> Result<(), Error> {
+    while let Some((_path, content)) = stream.next()? {
+        if let casync_format::Content::File(mut data) = content {
+            io::copy(&mut data, &mut io::sink())?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn chunk_missing() -> Result<(), Error> {
+    let (_, chunks) = read_index(fs::File::open("tests/data/nums.caidx")?)?;
+    let reader = from_index("tests/data/nums.caidx", |path: &str| {
+        if path.ends_with(".cacnk") {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "gone"));
+        }
+        fs::read(path)
+    })?;
+
+    match read_all(Stream::new(reader)) {
+        Err(Error::ChunkMissing { id }) => assert_eq!(chunks[0].id, id),
+        other => panic!("unexpected: {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn checksum_mismatch() -> Result<(), Error> {
+    let (_, chunks) = read_index(fs::File::open("tests/data/nums.caidx")?)?;
+    let first = chunks[0].format_id();
+    let second = chunks[1].format_id();
+    let reader = from_index("tests/data/nums.caidx", move |path: &str| {
+        // the store has the second chunk's content in the first's place
+        fs::read(path.replace(&first, &second))
+    })?;
+
+    match read_all(Stream::new(reader)) {
+        Err(Error::ChecksumMismatch { id, actual }) => {
+            assert_eq!(chunks[0].id, id);
+            assert_eq!(chunks[1].id, actual);
+        }
+        other => panic!("unexpected: {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn unknown_magic() {
+    let original = &include_bytes!("data/two.catar")[..];
+
+    // the `Name` packet after the root's `Entry`
+    let mut bad = original.to_vec();
+    bad[64 + 8] ^= 1;
+    match read_all(Stream::new(&bad[..])) {
+        Err(Error::UnknownMagic {
+            magic,
+            offset: 64,
+            path,
+        }) => {
+            assert_eq!(0x6dbb6ebcb3161f0a, magic);
+            assert_eq!(Some("."), path.as_deref());
+        }
+        other => panic!("unexpected: {:?}", other),
+    }
+}
+
+#[test]
+fn record_too_large() {
+    let mut bad = include_bytes!("data/two.catar").to_vec();
+    bad[64..72].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    match read_all(Stream::new(&bad[..])) {
+        Err(Error::RecordTooLarge {
+            size, offset: 64, ..
+        }) => assert_eq!(1 << 40, size),
+        other => panic!("unexpected: {:?}", other),
+    }
+}
+
+#[test]
+fn truncated() {
+    let original = &include_bytes!("data/two.catar")[..];
+
+    // part way through the first `Name`
+    match read_all(Stream::new(&original[..70])) {
+        Err(Error::Truncated { offset: 64, .. }) => (),
+        other => panic!("unexpected: {:?}", other),
+    }
+
+    // and part way through its header
+    match read_all(Stream::new(&original[..40])) {
+        Err(Error::Truncated { offset: 0, .. }) => (),
+        other => panic!("unexpected: {:?}", other),
+    }
+}
+
+#[test]
+fn malformed() {
+    let original = &include_bytes!("data/two.catar")[..];
+
+    // the size of the first item in the root's goodbye table
+    let mut bad_size = original.to_vec();
+    bad_size[0x241] ^= 1;
+    match read_all(Stream::new(&bad_size[..])) {
+        Err(Error::Malformed {
+            message,
+            offset: Some(_),
+            ..
+        }) => assert!(message.contains("goodbye"), "{}", message),
+        other => panic!("unexpected: {:?}", other),
+    }
+}
+
+#[test]
+fn entry_size() {
+    // the root's `Entry`, as if it had an extra field
+    let mut bad = include_bytes!("data/two.catar").to_vec();
+    bad[0..8].copy_from_slice(&72u64.to_le_bytes());
+    match read_all(Stream::new(&bad[..])) {
+        Err(Error::Malformed {
+            message,
+            offset: Some(0),
+            ..
+        }) => assert!(
+            message.contains("unsupported ENTRY size: 72 bytes; upstream's are 64"),
+            "{}",
+            message
+        ),
+        other => panic!("unexpected: {:?}", other),
+    }
+}
+
+#[test]
+fn located() {
+    let original = &include_bytes!("data/two.catar")[..];
+    let mut stream = Stream::new(original);
+    let mut offsets = Vec::new();
+    while let Some((_, content)) = stream.next().expect("valid") {
+        if let casync_format::Content::File(mut data) = content {
+            io::copy(&mut data, &mut io::sink()).expect("in memory");
+        }
+        offsets.push(stream.offset());
+    }
+    assert_eq!(original.len() as u64, *offsets.last().unwrap());
+
+    // empty the name of `b/three`, which starts at 0x92
+    let mut bad = original.to_vec();
+    bad[0x92..0x92 + 8].copy_from_slice(&17u64.to_le_bytes());
+    bad[0x92 + 16] = 0;
+    let err = read_all(Stream::new(&bad[..])).unwrap_err();
+    assert_eq!(Some(0x92), err.offset());
+    assert_eq!(Some("./b"), err.path());
+    assert_eq!(
+        "filename must be non-empty, at byte 146, in \"./b\"",
+        err.to_string()
+    );
+}
+
+/// impossible chunk sizes in an index are a bad file, not a bad request
+#[test]
+fn index_chunk_sizes() -> Result<(), Error> {
+    let mut bad = fs::read("tests/data/nums.caidx")?;
+    // the minimum chunk size
+    bad[24..32].copy_from_slice(&0u64.to_le_bytes());
+    match read_index(&bad[..]) {
+        Err(Error::Malformed {
+            offset: Some(24), ..
+        }) => (),
+        other => panic!("unexpected: {:?}", other.map(|(sizes, _)| sizes)),
+    }
+    Ok(())
+}
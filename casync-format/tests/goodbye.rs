@@ -0,0 +1,35 @@
+use anyhow::Error;
+
+use casync_format::Goodbye;
+use casync_format::GoodbyeItem;
+use casync_format::hash_name;
+
+#[test]
+fn hashes() {
+    // from two.catar, as written by casync
+    assert_eq!(0xd6cd88ee98895c7a, hash_name(b"two"));
+    assert_eq!(0xd443312ab802b82d, hash_name(b"three"));
+}
+
+#[test]
+fn round_trip() -> Result<(), Error> {
+    let items: Vec<GoodbyeItem> = (0..10u8)
+        .map(|n| GoodbyeItem {
+            hash: hash_name(&[n]),
+            offset: 100 - u64::from(n),
+            size: u64::from(n),
+        })
+        .collect();
+
+    let table = Goodbye::from_items(items.clone(), 1234);
+    assert!(table.is_search_tree());
+    assert_eq!(16 + 24 * 11, table.size);
+    assert_eq!(table, Goodbye::parse(&table.to_payload())?);
+
+    for item in &items {
+        assert_eq!(vec![item], table.find(item.hash));
+    }
+    assert!(table.find(7).is_empty());
+
+    Ok(())
+}
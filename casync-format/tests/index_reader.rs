@@ -0,0 +1,98 @@
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::rc::Rc;
+
+use anyhow::Error;
+
+use casync_format::CatarReader;
+use casync_format::Content;
+use casync_format::IndexReader;
+use casync_format::read_index;
+
+fn expected_blob() -> Vec<u8> {
+    let mut expected = Vec::new();
+    for i in 0..2000 {
+        expected.extend_from_slice(format!("line {}\n", i).as_bytes());
+    }
+    expected
+}
+
+#[test]
+fn seek_blob() -> Result<(), Error> {
+    let expected = expected_blob();
+    let (_sizes, chunks) = read_index(fs::File::open("tests/data/blob.caibx")?)?;
+
+    let fetches = Rc::new(Cell::new(0));
+    let counter = fetches.clone();
+    let mut reader = IndexReader::new(chunks, move |path: &str| {
+        counter.set(counter.get() + 1);
+        fs::read(format!("tests/data/blob.castr/{}", path))
+    })
+    .with_cache_chunks(1);
+    assert_eq!(expected.len() as u64, reader.len());
+
+    // across the boundary between the first and second chunks
+    reader.seek(SeekFrom::Start(4990))?;
+    let mut buf = [0u8; 20];
+    reader.read_exact(&mut buf)?;
+    assert_eq!(&expected[4990..5010], &buf[..]);
+    assert_eq!(2, fetches.get());
+
+    // still in the second chunk, so no fetch
+    reader.seek(SeekFrom::Current(100))?;
+    reader.read_exact(&mut buf)?;
+    assert_eq!(&expected[5110..5130], &buf[..]);
+    assert_eq!(2, fetches.get());
+
+    reader.seek(SeekFrom::End(-5))?;
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail)?;
+    assert_eq!(&expected[expected.len() - 5..], &tail[..]);
+    assert_eq!(3, fetches.get());
+
+    assert!(reader.seek(SeekFrom::Current(-100_000)).is_err());
+
+    reader.seek(SeekFrom::Start(0))?;
+    let mut all = Vec::new();
+    reader.read_to_end(&mut all)?;
+    assert_eq!(expected, all);
+
+    Ok(())
+}
+
+#[test]
+fn lookup_through_index() -> Result<(), Error> {
+    let (_sizes, chunks) = read_index(fs::File::open("tests/data/nums.caidx")?)?;
+    let reader = IndexReader::new(chunks, |path: &str| {
+        fs::read(format!("tests/data/nums.castr/{}", path))
+    });
+
+    let mut catar = CatarReader::new(reader)?;
+    match catar.lookup("data")? {
+        Some((_, Content::File(mut data))) => {
+            let mut buf = String::new();
+            data.read_to_string(&mut buf)?;
+            let expected: String = (1..=10000).map(|i| format!("{}\n", i)).collect();
+            assert_eq!(expected, buf);
+        }
+        _ => panic!("data is a file"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn wrong_length() -> Result<(), Error> {
+    let (_sizes, mut chunks) = read_index(fs::File::open("tests/data/blob.caibx")?)?;
+    chunks[0].offset -= 1;
+    let mut reader = IndexReader::new(chunks, |path: &str| {
+        fs::read(format!("tests/data/blob.castr/{}", path))
+    });
+    let err = reader.read(&mut [0u8; 10]).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+
+use anyhow::Error;
+
+use casync_format::ChunkDigest;
+use casync_format::Chunks;
+use casync_format::FeatureFlags;
+use casync_format::IndexWriter;
+use casync_format::chunks::from_index;
+use casync_format::read_index;
+
+fn feature_flags(index: &[u8]) -> Result<FeatureFlags, Error> {
+    let mut flags = [0u8; 8];
+    flags.copy_from_slice(&index[16..24]);
+    Ok(FeatureFlags::from_raw(u64::from_le_bytes(flags))?)
+}
+
+/// byte-for-byte what upstream wrote
+#[test]
+fn round_trip() -> Result<(), Error> {
+    for path in &[
+        "tests/data/trivial.caidx",
+        "tests/data/nums.caidx",
+        "tests/data/blob.caibx",
+    ] {
+        let original = fs::read(path)?;
+        let (sizes, chunks) = read_index(io::Cursor::new(&original))?;
+
+        let mut writer = IndexWriter::new(Vec::new(), feature_flags(&original)?, sizes)?;
+        for chunk in &chunks {
+            writer.push(chunk.offset, &chunk.id)?;
+        }
+        let written = writer.finish()?;
+
+        assert_eq!(original, written, "{}", path);
+    }
+    Ok(())
+}
+
+/// chunk the stream again, and get the same index
+#[test]
+fn rechunk_nums() -> Result<(), Error> {
+    let original = fs::read("tests/data/nums.caidx")?;
+    let (sizes, _) = read_index(io::Cursor::new(&original))?;
+
+    let mut stream = Vec::new();
+    from_index("tests/data/nums.caidx", |path: &str| fs::read(path))?.read_to_end(&mut stream)?;
+
+    let mut writer = IndexWriter::new(Vec::new(), feature_flags(&original)?, sizes)?;
+    for chunk in Chunks::new(io::Cursor::new(&stream), sizes) {
+        writer.add(&chunk?)?;
+    }
+    assert_eq!(stream.len() as u64, writer.len());
+
+    assert_eq!(original, writer.finish()?);
+    Ok(())
+}
+
+#[test]
+fn out_of_order() -> Result<(), Error> {
+    let (sizes, _) = read_index(fs::File::open("tests/data/trivial.caidx")?)?;
+    let mut writer = IndexWriter::new(Vec::new(), FeatureFlags::SHA512_256, sizes)?;
+    writer.push(10, &[0u8; 32])?;
+    assert!(writer.push(10, &[0u8; 32]).is_err());
+    assert!(writer.push(5, &[0u8; 32]).is_err());
+    assert!(writer.add(&[]).is_err());
+
+    // an empty stream is still a valid index
+    let empty = IndexWriter::new(Vec::new(), FeatureFlags::SHA512_256, sizes)?.finish()?;
+    let (_, chunks) = read_index(io::Cursor::new(&empty))?;
+    assert!(chunks.is_empty());
+    Ok(())
+}
+
+#[test]
+fn digests() -> Result<(), Error> {
+    let (sizes, chunks) = read_index(fs::File::open("tests/data/nums.caidx")?)?;
+    assert!(chunks.iter().all(|c| ChunkDigest::Sha512_256 == c.digest));
+
+    // without the flag
+    let mut writer = IndexWriter::new(Vec::new(), FeatureFlags::WITH_32BIT_UIDS, sizes)?;
+    let added = writer.add(b"hello")?;
+    assert_eq!(ChunkDigest::Sha256, added.digest);
+    assert_eq!(
+        "2cf2/2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824.cacnk",
+        added.format_id()
+    );
+
+    let (_, chunks) = read_index(io::Cursor::new(writer.finish()?))?;
+    assert_eq!(vec![added], chunks);
+    chunks[0].check(b"hello")?;
+    assert!(chunks[0].check(b"world").is_err());
+    Ok(())
+}
+
+#[test]
+fn unsupported_flags() -> Result<(), Error> {
+    let mut index = fs::read("tests/data/trivial.caidx")?;
+    index[16 + 5] |= 0x40;
+    let err = read_index(io::Cursor::new(&index)).unwrap_err();
+    assert_eq!("unsupported feature flags: 0x400000000000", err.to_string());
+    assert!(matches!(
+        err,
+        casync_format::Error::UnsupportedFeatureFlags {
+            flags: 0x4000_0000_0000
+        }
+    ));
+    Ok(())
+}
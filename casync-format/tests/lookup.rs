@@ -0,0 +1,137 @@
+use std::io;
+use std::io::Read;
+
+use anyhow::Error;
+
+use casync_format::CatarReader;
+use casync_format::Content;
+
+fn read_file<R: Read + io::Seek>(reader: &mut CatarReader<R>, path: &str) -> Result<String, Error> {
+    match reader.lookup(path)? {
+        Some((entry, Content::File(mut data))) => {
+            assert!(entry.is_reg());
+            let mut buf = String::new();
+            data.read_to_string(&mut buf)?;
+            Ok(buf)
+        }
+        other => panic!(
+            "{} wasn't a file: {:?}",
+            path,
+            other.map(|(entry, _)| entry)
+        ),
+    }
+}
+
+#[test]
+fn two() -> Result<(), Error> {
+    let file = &include_bytes!("data/two.catar")[..];
+    let mut reader = CatarReader::new(io::Cursor::new(file))?;
+
+    assert_eq!("world\n", read_file(&mut reader, "b/two")?);
+    assert_eq!("hello\n", read_file(&mut reader, "one")?);
+    assert_eq!("cats\n", read_file(&mut reader, "./b//three")?);
+
+    match reader.lookup("b")? {
+        Some((entry, Content::Directory)) => assert!(entry.is_dir()),
+        _ => panic!("b is a directory"),
+    }
+    match reader.lookup("")? {
+        Some((entry, Content::Directory)) => assert_eq!(0o755, entry.mode & 0o7777),
+        _ => panic!("root is a directory"),
+    }
+
+    assert!(reader.lookup("missing")?.is_none());
+    assert!(reader.lookup("b/missing")?.is_none());
+    assert!(reader.lookup("one/two").is_err());
+    assert!(reader.lookup("b/../one").is_err());
+    Ok(())
+}
+
+/// hand-assembled: files `0` to `99`, and `sub/deeper/leaf` and `sub/empty/`
+#[test]
+fn many() -> Result<(), Error> {
+    let file = &include_bytes!("data/many.catar")[..];
+    let mut reader = CatarReader::new(io::Cursor::new(file))?;
+
+    for i in (0..100).rev() {
+        assert_eq!(format!("{}\n", i), read_file(&mut reader, &i.to_string())?);
+    }
+
+    assert_eq!("leaf\n", read_file(&mut reader, "sub/deeper/leaf")?);
+    assert!(matches!(
+        reader.lookup("sub/empty")?,
+        Some((_, Content::Directory))
+    ));
+    assert!(reader.lookup("sub/empty/nothing")?.is_none());
+    assert!(reader.lookup("100")?.is_none());
+
+    // and the goodbye tables agree with a full read
+    let mut stream = casync_format::Stream::new(file);
+    let mut items = 0;
+    while let Some((_path, content)) = stream.next()? {
+        if let Content::File(mut data) = content {
+            io::copy(&mut data, &mut io::sink())?;
+        }
+        items += 1;
+    }
+    assert_eq!(100 + 5, items);
+    Ok(())
+}
+
+#[test]
+fn special() -> Result<(), Error> {
+    let file = &include_bytes!("data/special.catar")[..];
+    let mut reader = CatarReader::new(io::Cursor::new(file))?;
+
+    match reader.lookup("link")? {
+        Some((_, Content::Symlink(target))) => assert_eq!(&b"file"[..], &target[..]),
+        _ => panic!("link is a symlink"),
+    }
+    assert!(matches!(
+        reader.lookup("dir/pipe")?,
+        Some((_, Content::Fifo))
+    ));
+    assert!(matches!(reader.lookup("sock")?, Some((_, Content::Socket))));
+    assert!(matches!(
+        reader.lookup("sda")?,
+        Some((_, Content::Device { major: 8, minor: 0 }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn metadata() -> Result<(), Error> {
+    let file = &include_bytes!("data/metadata.catar")[..];
+    let mut reader = CatarReader::new(io::Cursor::new(file))?;
+
+    let (entry, _) = reader.lookup("ping")?.expect("ping");
+    assert_eq!(2, entry.xattrs.len());
+    assert!(entry.fcaps.is_some());
+    Ok(())
+}
+
+/// a goodbye item's size is only trusted as far as it's sane
+#[test]
+fn huge_child() -> Result<(), Error> {
+    let mut file = include_bytes!("data/two.catar").to_vec();
+    let root_bye = casync_format::PacketReader::new(&file[..])
+        .last()
+        .expect("packets")?;
+    assert_eq!(casync_format::StreamMagic::Bye, root_bye.magic);
+
+    // every item but the tail: offset, size, hash
+    let items = (root_bye.size - 16) / 24 - 1;
+    for item in 0..items {
+        let size = (root_bye.offset + 16 + item * 24 + 8) as usize;
+        file[size..size + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    }
+
+    let mut reader = CatarReader::new(io::Cursor::new(&file[..]))?;
+    match reader.lookup("b/two") {
+        Err(casync_format::Error::Malformed { message, .. }) => {
+            assert!(message.contains("overflows"), "{}", message)
+        }
+        other => panic!("unexpected: {:?}", other.map(|found| found.map(|(e, _)| e))),
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use casync_format::Error;
+use casync_format::PacketReader;
+use casync_format::StreamMagic;
+
+#[test]
+fn two() -> Result<(), Error> {
+    let original = &include_bytes!("data/two.catar")[..];
+    let packets = PacketReader::new(original).collect::<Result<Vec<_>, _>>()?;
+
+    let offsets: Vec<u64> = packets.iter().map(|packet| packet.offset).collect();
+    assert_eq!(
+        vec![
+            0, 64, 82, 146, 168, 232, 253, 273, 337, 359, 447, 467, 531, 553
+        ],
+        offsets
+    );
+    let end = packets.last().unwrap();
+    assert_eq!(original.len() as u64, end.offset + end.size);
+    assert_eq!(StreamMagic::Bye, end.magic);
+
+    let names: Vec<&[u8]> = packets
+        .iter()
+        .filter(|packet| StreamMagic::Name == packet.magic)
+        .filter_map(|packet| packet.text())
+        .collect();
+    assert_eq!(vec![&b"b"[..], b"three", b"two", b"one"], names);
+
+    // the file content is skipped
+    assert_eq!(StreamMagic::Data, packets[5].magic);
+    assert_eq!(21, packets[5].size);
+    assert!(packets[5].payload.is_empty());
+
+    assert!(packets[0].entry()?.is_dir());
+    assert!(packets[1].entry().is_err());
+    Ok(())
+}
+
+#[test]
+fn stops_at_errors() {
+    let original = &include_bytes!("data/two.catar")[..];
+
+    // part way through the first file's data
+    let mut packets = PacketReader::new(&original[..240]);
+    assert_eq!(5, packets.by_ref().take_while(Result::is_ok).count());
+    assert!(packets.next().is_none());
+
+    let mut packets = PacketReader::new(&original[..240]).skip(5);
+    match packets.next() {
+        Some(Err(Error::Truncated { offset: 232, .. })) => (),
+        other => panic!("unexpected: {:?}", other),
+    }
+
+    let mut bad = original.to_vec();
+    bad[64 + 8] ^= 1;
+    let mut packets = PacketReader::new(&bad[..]);
+    assert!(packets.next().unwrap().is_ok());
+    match packets.next() {
+        Some(Err(Error::UnknownMagic { offset: 64, .. })) => (),
+        other => panic!("unexpected: {:?}", other),
+    }
+    assert!(packets.next().is_none());
+}
+
+#[test]
+fn devices() -> Result<(), Error> {
+    let mut devices = Vec::new();
+    for packet in PacketReader::new(&include_bytes!("data/special.catar")[..]) {
+        let packet = packet?;
+        assert_eq!(
+            StreamMagic::Device == packet.magic,
+            packet.device().is_some()
+        );
+        devices.extend(packet.device());
+    }
+    assert_eq!(vec![(1, 3), (8, 0)], devices);
+    Ok(())
+}
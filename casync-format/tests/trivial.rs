@@ -55,15 +55,198 @@ fn load_nums() -> Result<(), Error> {
 
         paths.push(casync_format::utf8_path(names).unwrap());
 
-        match content {
+        if let casync_format::Content::File(mut data) = content {
+            let mut buf = Vec::new();
+            data.read_to_end(&mut buf).unwrap();
+        }
+    }
+
+    assert_eq!(&["./data".to_string(), ".".to_string(),], paths.as_slice());
+    Ok(())
+}
+
+/// hand-assembled in the layout `casync make --with=symlinks,device-nodes,fifos,sockets` uses:
+/// dir/pipe (fifo), fifo, file, link -> file, null (c 1:3), sda (b 8:0), sock (socket)
+#[test]
+fn special_files() -> Result<(), Error> {
+    let file = &include_bytes!("data/special.catar")[..];
+    let mut stream = Stream::new(io::Cursor::new(file));
+
+    let mut seen = Vec::new();
+    while let Some((path, content)) = stream.next()? {
+        let entry = path.end().entry.clone().expect("entry");
+        let names: Vec<Box<[u8]>> = path.into_iter().map(|item| item.name).collect();
+        let name = casync_format::utf8_path(names)?;
+        let desc = match content {
             casync_format::Content::File(mut data) => {
-                let mut buf = Vec::new();
-                data.read_to_end(&mut buf).unwrap();
+                assert!(entry.is_reg());
+                let mut buf = String::new();
+                data.read_to_string(&mut buf)?;
+                format!("file {:?}", buf)
+            }
+            casync_format::Content::Directory => {
+                assert!(entry.is_dir());
+                "dir".to_string()
+            }
+            casync_format::Content::Symlink(target) => {
+                assert!(entry.is_lnk());
+                format!("link {}", String::from_utf8_lossy(&target))
+            }
+            casync_format::Content::Device { major, minor } => format!(
+                "{} {}:{}",
+                if entry.is_chr() { "chr" } else { "blk" },
+                major,
+                minor
+            ),
+            casync_format::Content::Fifo => {
+                assert!(entry.is_fifo());
+                "fifo".to_string()
+            }
+            casync_format::Content::Socket => {
+                assert!(entry.is_sock());
+                "sock".to_string()
+            }
+            casync_format::Content::Hardlink(target) => {
+                assert!(entry.is_reg());
+                format!("hardlink {}", String::from_utf8_lossy(&target))
             }
-            casync_format::Content::Directory => {}
+        };
+        seen.push(format!("{} {}", name, desc));
+    }
+
+    assert_eq!(
+        vec![
+            "./dir/pipe fifo",
+            "./dir dir",
+            "./fifo fifo",
+            "./file file \"hello\\n\"",
+            "./link link file",
+            "./null chr 1:3",
+            "./sda blk 8:0",
+            "./sock sock",
+            ". dir",
+        ],
+        seen
+    );
+    Ok(())
+}
+
+/// hand-assembled: a directory with a default ACL and an xattr,
+/// containing `ping`, with xattrs, ACLs, an SELinux label and file capabilities
+#[test]
+fn metadata() -> Result<(), Error> {
+    use casync_format::AclEntry;
+
+    let file = &include_bytes!("data/metadata.catar")[..];
+    let mut stream = Stream::new(io::Cursor::new(file));
+
+    let (path, content) = stream.next()?.expect("ping");
+    let ping = path.end().entry.clone().expect("entry");
+    if let casync_format::Content::File(mut data) = content {
+        io::copy(&mut data, &mut io::sink())?;
+    }
+
+    assert_eq!(Some(&b"faux"[..]), ping.user_name.as_deref());
+    assert_eq!(
+        vec![
+            (b"user.a".to_vec(), b"\x00\x01binary".to_vec()),
+            (b"user.b".to_vec(), Vec::new()),
+        ],
+        ping.xattrs
+            .iter()
+            .map(|(k, v)| (k.to_vec(), v.to_vec()))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![AclEntry {
+            id: 1001,
+            permissions: casync_format::ACL_READ | casync_format::ACL_WRITE,
+            name: Some(b"alice".to_vec().into_boxed_slice()),
+        }],
+        ping.acl.user
+    );
+    assert_eq!(1002, ping.acl.group[0].id);
+    assert_eq!(Some(casync_format::ACL_READ), ping.acl.group_obj);
+    assert_eq!(None, ping.acl.default);
+    assert_eq!(
+        Some(&b"system_u:object_r:bin_t:s0"[..]),
+        ping.selinux.as_deref()
+    );
+    assert_eq!(20, ping.fcaps.expect("fcaps").len());
+
+    let (path, _) = stream.next()?.expect("root");
+    let root = path.end().entry.clone().expect("entry");
+    assert_eq!(1, root.xattrs.len());
+    assert_eq!(7, root.acl.default.expect("default acl").mask_permissions);
+    assert_eq!(
+        Some(&b"alice"[..]),
+        root.acl.default_user[0].name.as_deref()
+    );
+    assert_eq!(None, root.acl.default_group[0].name);
+    assert!(root.fcaps.is_none());
+
+    assert!(stream.next()?.is_none());
+    Ok(())
+}
+
+fn read_all<R: Read>(mut stream: Stream<R>) -> Result<(), Error> {
+    while let Some((_path, content)) = stream.next()? {
+        if let casync_format::Content::File(mut data) = content {
+            io::copy(&mut data, &mut io::sink())?;
         }
     }
+    Ok(())
+}
 
-    assert_eq!(&["./data".to_string(), ".".to_string(),], paths.as_slice());
+#[test]
+fn corrupt_goodbye() {
+    let original = &include_bytes!("data/two.catar")[..];
+    read_all(Stream::new(original)).expect("valid");
+
+    // the size of the first item in the root's goodbye table
+    let mut bad_size = original.to_vec();
+    bad_size[0x241] ^= 1;
+    let err = read_all(Stream::new(&bad_size[..])).unwrap_err();
+    assert!(err.to_string().contains("goodbye"), "{}", err);
+
+    // renaming "one" to "onf" invalidates its hash
+    let mut renamed = original.to_vec();
+    renamed[0x1d1] = b'f';
+    let err = read_all(Stream::new(&renamed[..])).unwrap_err();
+    assert!(err.to_string().contains("goodbye"), "{}", err);
+
+    // and the tail has to point back to the directory's entry
+    let mut bad_tail = original.to_vec();
+    bad_tail[0x1a7] ^= 1;
+    let err = read_all(Stream::new(&bad_tail[..])).unwrap_err();
+    assert!(err.to_string().contains("entry"), "{}", err);
+}
+
+/// hand-assembled: `seq`-like lines, split into three chunks
+#[test]
+fn load_blob() -> Result<(), Error> {
+    let mut expected = String::new();
+    for i in 0..2000 {
+        expected.push_str(&format!("line {}\n", i));
+    }
+
+    let mut blob = String::new();
+    from_index("tests/data/blob.caibx", |path: &str| fs::read(path))?.read_to_string(&mut blob)?;
+    assert_eq!(expected, blob);
+
+    let file = io::Cursor::new(&include_bytes!("data/blob.caibx")[..]);
+    let (_sizes, chunks) = casync_format::read_index(file)?;
+    assert_eq!(
+        vec![5000, 12000, expected.len() as u64],
+        chunks.iter().map(|c| c.offset).collect::<Vec<_>>()
+    );
     Ok(())
 }
+
+#[test]
+fn index_kind() {
+    use casync_format::IndexKind;
+    assert_eq!(IndexKind::Catar, IndexKind::from_path("a/b.caidx").unwrap());
+    assert_eq!(IndexKind::Blob, IndexKind::from_path("b.caibx").unwrap());
+    assert!(IndexKind::from_path("b.catar").is_err());
+}
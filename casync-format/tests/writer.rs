@@ -0,0 +1,210 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use anyhow::Error;
+
+use casync_format::CatarWriter;
+use casync_format::Content;
+use casync_format::Entry;
+use casync_format::FeatureFlags;
+use casync_format::Stream;
+use casync_format::Timestamp;
+
+/// read a whole archive with `Stream`, and write it out again with `CatarWriter`
+fn rewrite(archive: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut stream = Stream::new(io::Cursor::new(archive));
+    let mut writer: Option<CatarWriter<Vec<u8>>> = None;
+    // the names of the directories we've begun
+    let mut open: Vec<Box<[u8]>> = Vec::new();
+
+    while let Some((path, content)) = stream.next()? {
+        let items: Vec<_> = path.into_iter().collect();
+        let (item, parents) = items.split_last().unwrap();
+        let entry = item.entry.clone().unwrap();
+
+        // `Stream` only tells us about a directory when it ends
+        let writer = match (&mut writer, parents.first()) {
+            (Some(writer), _) => writer,
+            (None, root) => writer.insert(CatarWriter::new(
+                Vec::new(),
+                root.unwrap_or(item).entry.as_ref().unwrap(),
+            )?),
+        };
+        for parent in parents.iter().skip(1 + open.len()) {
+            writer.begin_dir(&parent.name, parent.entry.as_ref().unwrap())?;
+            open.push(parent.name.clone());
+        }
+
+        match content {
+            Content::File(data) => writer.add_file(&item.name, &entry, data)?,
+            Content::Directory if parents.is_empty() => {}
+            Content::Directory if parents.len() == open.len() => {
+                assert_eq!(Some(&item.name), open.last());
+                writer.end_dir()?;
+                open.pop();
+            }
+            Content::Directory => {
+                writer.begin_dir(&item.name, &entry)?;
+                writer.end_dir()?;
+            }
+            Content::Symlink(target) => writer.add_symlink(&item.name, &entry, &target)?,
+            Content::Device { major, minor } => {
+                writer.add_device(&item.name, &entry, major, minor)?
+            }
+            Content::Fifo | Content::Socket => writer.add_special(&item.name, &entry)?,
+            Content::Hardlink(target) => writer.add_hardlink(&item.name, &entry, &target)?,
+        }
+    }
+
+    Ok(writer.unwrap().finish()?)
+}
+
+/// byte-for-byte what upstream, or our generator, wrote
+#[test]
+fn rewrite_fixtures() -> Result<(), Error> {
+    for path in &[
+        "tests/hello-world.catar",
+        "tests/data/two.catar",
+        "tests/data/many.catar",
+        "tests/data/special.catar",
+        "tests/data/metadata.catar",
+    ] {
+        let original = fs::read(path)?;
+        assert_eq!(original, rewrite(&original)?, "{}", path);
+    }
+    Ok(())
+}
+
+fn entry(mode: u64) -> Entry {
+    Entry {
+        mode,
+        mtime: Timestamp::from_nanos(1_500_000_000_123_456_789),
+        user_name: Some(b"faux"[..].into()),
+        ..Entry::default()
+    }
+}
+
+#[test]
+fn in_memory() -> Result<(), Error> {
+    let mut writer = CatarWriter::new(Vec::new(), &entry(0o40755))?;
+    writer.begin_dir(b"etc", &entry(0o40755))?;
+    writer.begin_dir(b"empty", &entry(0o40700))?;
+    writer.end_dir()?;
+    writer.add_file(b"hostname", &entry(0o100644), &b"example\n"[..])?;
+    writer.add_symlink(b"localtime", &entry(0o120777), b"/usr/share/zoneinfo/UTC")?;
+    writer.end_dir()?;
+    writer.add_file(b"readme", &entry(0o100600), io::empty())?;
+    writer.add_hardlink(b"readme-too", &entry(0o100600), b"readme")?;
+    let archive = writer.finish()?;
+
+    let mut found = Vec::new();
+    let mut stream = Stream::new(io::Cursor::new(&archive));
+    while let Some((path, content)) = stream.next()? {
+        let entry = path.end().entry.clone().unwrap();
+        let names: Vec<Box<[u8]>> = path.into_iter().map(|item| item.name).collect();
+        let content = match content {
+            Content::File(mut data) => {
+                let mut buf = String::new();
+                data.read_to_string(&mut buf)?;
+                buf
+            }
+            Content::Symlink(target) => String::from_utf8(target.into_vec())?,
+            Content::Hardlink(target) => format!("-> {}", String::from_utf8(target.into_vec())?),
+            _ => String::new(),
+        };
+        assert_eq!(1_500_000_000_123_456_789, entry.mtime.as_nanos());
+        assert_eq!(Some(&b"faux"[..]), entry.user_name.as_deref());
+        found.push((
+            casync_format::utf8_path(names)?,
+            format!("{:o}", entry.mode),
+            content,
+        ));
+    }
+
+    let expected = [
+        ("./etc/empty", "40700", ""),
+        ("./etc/hostname", "100644", "example\n"),
+        ("./etc/localtime", "120777", "/usr/share/zoneinfo/UTC"),
+        ("./etc", "40755", ""),
+        ("./readme", "100600", ""),
+        ("./readme-too", "100600", "-> readme"),
+        (".", "40755", ""),
+    ];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(a, b, c)| (a.to_string(), b.to_string(), c.to_string()))
+        .collect();
+    assert_eq!(expected, found);
+
+    // and the goodbye tables work for lookups
+    let mut reader = casync_format::CatarReader::new(io::Cursor::new(&archive))?;
+    assert!(reader.lookup("etc/localtime")?.is_some());
+    assert!(reader.lookup("etc/missing")?.is_none());
+    Ok(())
+}
+
+#[test]
+fn misuse() -> Result<(), Error> {
+    let dir = entry(0o40755);
+    let file = entry(0o100644);
+
+    assert!(CatarWriter::new(Vec::new(), &file).is_err());
+
+    let mut writer = CatarWriter::new(Vec::new(), &dir)?;
+    assert!(writer.end_dir().is_err(), "the root is ended by finish");
+    assert!(writer.add_file(b"a", &dir, io::empty()).is_err());
+    assert!(writer.begin_dir(b"a", &file).is_err());
+    assert!(writer.add_file(b"..", &file, io::empty()).is_err());
+    assert!(writer.add_file(b"a/b", &file, io::empty()).is_err());
+    writer.add_file(b"b", &file, io::empty())?;
+    assert!(writer.add_file(b"b", &file, io::empty()).is_err());
+    assert!(writer.add_file(b"a", &file, io::empty()).is_err());
+    assert!(writer.add_hardlink(b"d", &file, b"../b").is_err());
+    assert!(writer.add_hardlink(b"d", &file, b"/b").is_err());
+    assert!(writer.add_hardlink(b"d", &dir, b"b").is_err());
+    assert!(
+        writer
+            .add_file_with_len(b"c", &file, 10, &b"short"[..])
+            .is_err()
+    );
+
+    let mut writer = CatarWriter::new(Vec::new(), &dir)?;
+    writer.begin_dir(b"open", &dir)?;
+    assert!(writer.finish().is_err());
+
+    // nanoseconds, in an archive which claims to only have seconds
+    let coarse = Entry {
+        feature_flags: FeatureFlags::WITH_SEC_TIME,
+        ..entry(0o40755)
+    };
+    assert!(CatarWriter::new(Vec::new(), &coarse).is_err());
+    Ok(())
+}
+
+#[test]
+fn timestamps() {
+    let time = Timestamp::from_nanos(1_500_000_000_123_456_789);
+    assert_eq!(1_500_000_000, time.secs());
+    assert_eq!(123_456_789, time.subsec_nanos());
+    assert_eq!("1500000000.123456789", time.to_string());
+    assert_eq!(time, Timestamp::try_from(SystemTime::from(time)).unwrap());
+    assert!(Timestamp::try_from(SystemTime::UNIX_EPOCH - Duration::from_secs(1)).is_err());
+
+    let granularity = |flags| Timestamp::granularity(flags).map(|g| time.truncate(g).as_nanos());
+    assert_eq!(
+        Some(1_500_000_000_123_456_789),
+        granularity(FeatureFlags::WITH_NSEC_TIME | FeatureFlags::WITH_SEC_TIME)
+    );
+    assert_eq!(
+        Some(1_500_000_000_123_456_000),
+        granularity(FeatureFlags::WITH_USEC_TIME)
+    );
+    assert_eq!(
+        Some(1_500_000_000_000_000_000),
+        granularity(FeatureFlags::WITH_2SEC_TIME)
+    );
+    assert_eq!(None, granularity(FeatureFlags::WITH_USER_NAMES));
+}
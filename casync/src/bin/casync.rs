@@ -1,9 +1,22 @@
+use std::env;
+use std::fs;
 use std::io;
+use std::io::Write;
+use std::path::PathBuf;
 
 use anyhow::Error;
+use anyhow::bail;
+use casync::ChunkSource;
+use casync::CommitInfo;
+use casync::DumpFormat;
+use casync::Hardlinks;
+use casync::Owners;
+use casync_format::ChunkDigest;
+use casync_format::ChunkSize;
 use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
+use clap::ValueEnum;
 
 #[derive(Parser)]
 #[command(name = "casync-rs")]
@@ -22,24 +35,205 @@ enum Command {
         /// prefix for ref; index of argument appended
         #[arg(long)]
         ref_prefix: String,
+
+        /// the author and committer of every commit
+        #[arg(long, default_value = "casync-rs <solo-casync-rs@goeswhere.com>")]
+        author: String,
+
+        /// the commit time, in seconds since the epoch; by default, the time of the archive's root
+        #[arg(long)]
+        date: Option<i64>,
+
+        /// the commit message; by default, the index's location
+        #[arg(long, short, default_value = "")]
+        message: String,
     },
 
-    /// dump data about some archives
+    /// describe some archives as mtree specs, for `mtree -f`
     Mtree {
         #[command(flatten)]
         indexes: Indexes,
     },
+
+    /// archive a directory into a .caidx, or a file into a .caibx, adding its chunks to a store
+    Make {
+        /// the index file to write (.caidx or .caibx)
+        index: String,
+
+        /// the directory, or file, to archive
+        source: PathBuf,
+
+        /// the castore to add the chunks to
+        #[arg(long)]
+        store: String,
+
+        /// the average chunk size, in bytes; chunks are between a quarter and four times this
+        #[arg(long, default_value_t = 64 * 1024)]
+        chunk_size: u64,
+
+        /// how chunk ids are calculated; sha512-256 is upstream's default
+        #[arg(long, value_enum, default_value_t = Digest::Sha512_256)]
+        digest: Digest,
+
+        /// store files with several names once, as hardlinks, which upstream casync can't read
+        #[arg(long)]
+        hardlinks: bool,
+    },
+
+    /// unpack a .caidx into a directory
+    Extract {
+        /// the index file, or url, to unpack
+        index: String,
+
+        /// the directory to unpack into; created if necessary
+        target: PathBuf,
+
+        #[command(flatten)]
+        source: Source,
+
+        /// don't set the owners of the extracted files; the default unless running as root
+        #[arg(long)]
+        no_owner: bool,
+
+        /// set owners by the uid and gid in the archive; the default when running as root
+        #[arg(long, conflicts_with = "no_owner")]
+        numeric_owner: bool,
+
+        /// set owners by the user and group names in the archive, where they exist locally
+        #[arg(long, conflicts_with_all = ["no_owner", "numeric_owner"])]
+        by_name: bool,
+    },
+
+    /// delete the chunks in a store which none of the indexes reference
+    Gc {
+        /// every index whose chunks should be kept, as files or urls
+        #[arg(required = true)]
+        indexes: Vec<String>,
+
+        /// the local castore to delete from
+        #[arg(long)]
+        store: String,
+
+        /// list what would be deleted, without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// check that a store has every chunk an index references, intact
+    Verify {
+        /// the index to check, as a file or url
+        index: String,
+
+        /// the local castore to check
+        #[arg(long)]
+        store: String,
+
+        /// also read the whole archive a .caidx describes, to check its structure
+        #[arg(long)]
+        archive: bool,
+    },
+
+    /// describe every packet in an archive, for debugging
+    Dump {
+        /// a .catar, or, with --store, an index file or url
+        archive: String,
+
+        /// the castore which the index references: a directory, or a http(s):// url
+        #[arg(long)]
+        store: Option<String>,
+
+        /// where to keep chunks downloaded from a remote store
+        #[arg(long, requires = "store")]
+        cache_dir: Option<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+
+    /// write out the stream an index describes, e.g. a disk image from a .caibx
+    Cat {
+        /// the index file, or url (.caibx or .caidx)
+        index: String,
+
+        #[command(flatten)]
+        source: Source,
+
+        /// write to this file, instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Digest {
+    Sha256,
+    #[value(name = "sha512-256")]
+    Sha512_256,
+}
+
+impl From<Digest> for ChunkDigest {
+    fn from(digest: Digest) -> ChunkDigest {
+        match digest {
+            Digest::Sha256 => ChunkDigest::Sha256,
+            Digest::Sha512_256 => ChunkDigest::Sha512_256,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl From<Format> for DumpFormat {
+    fn from(format: Format) -> DumpFormat {
+        match format {
+            Format::Text => DumpFormat::Text,
+            Format::Json => DumpFormat::Json,
+        }
+    }
 }
 
 #[derive(Args)]
 struct Indexes {
-    /// the index file(s) to inspect
+    /// the index file(s), or urls, to inspect
     #[arg(required = true)]
     caidx: Vec<String>,
 
-    /// the castore which the indexes reference
+    #[command(flatten)]
+    source: Source,
+}
+
+#[derive(Args)]
+struct Source {
+    /// the castore which the index(es) reference: a directory, or a http(s):// url
     #[arg(long)]
     store: String,
+
+    /// where to keep chunks downloaded from a remote store;
+    /// by default, casync-rs in the user's cache directory
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+}
+
+impl Source {
+    fn chunks(&self) -> Result<ChunkSource, Error> {
+        let cache_dir = match &self.cache_dir {
+            Some(dir) => dir.clone(),
+            None => default_cache_dir(),
+        };
+        ChunkSource::new(&self.store, cache_dir)
+    }
+}
+
+fn default_cache_dir() -> PathBuf {
+    let base = match (env::var_os("XDG_CACHE_HOME"), env::var_os("HOME")) {
+        (Some(cache), _) if !cache.is_empty() => PathBuf::from(cache),
+        (_, Some(home)) if !home.is_empty() => PathBuf::from(home).join(".cache"),
+        _ => env::temp_dir(),
+    };
+    base.join("casync-rs")
 }
 
 fn main() -> Result<(), Error> {
@@ -49,30 +243,124 @@ fn main() -> Result<(), Error> {
         Command::FastExport {
             indexes,
             ref_prefix,
+            author,
+            date,
+            message,
         } => {
-            println!("feature done");
-
-            for (nth, caidx) in indexes.caidx.iter().enumerate() {
-                println!("# {}", caidx);
-                println!("commit {}{}", ref_prefix, nth);
-
-                // TODO: recover dates or even authors
-                println!("committer casync-rs <solo-casync-rs@goeswhere.com> 0 +0000");
-
-                // commit message: 0 bytes
-                println!("data 0");
-                println!();
-                println!("deleteall");
-
-                casync::tools::fast_export(io::stdout(), &indexes.store, caidx)?;
-            }
-
-            println!("done");
+            let info = CommitInfo {
+                author,
+                time: date,
+                message,
+            };
+            casync::tools::fast_export(
+                io::stdout().lock(),
+                &indexes.source.chunks()?,
+                &indexes.caidx,
+                &ref_prefix,
+                &info,
+            )?
+            .flush()?;
         }
         Command::Mtree { indexes } => {
+            let source = indexes.source.chunks()?;
             for caidx in &indexes.caidx {
-                casync::tools::mtree(io::stdout(), &indexes.store, caidx)?;
+                casync::tools::mtree(io::stdout(), &source, caidx)?;
+            }
+        }
+        Command::Make {
+            index,
+            source,
+            store,
+            chunk_size,
+            digest,
+            hardlinks,
+        } => {
+            let sizes = ChunkSize::from_avg(chunk_size)?;
+            let hardlinks = if hardlinks {
+                Hardlinks::Link
+            } else {
+                Hardlinks::Copy
+            };
+            let stats =
+                casync::tools::make(&store, &index, &source, sizes, digest.into(), hardlinks)?;
+            eprintln!("{}", stats);
+        }
+        Command::Extract {
+            index,
+            target,
+            source,
+            no_owner,
+            numeric_owner,
+            by_name,
+        } => {
+            let owners = if no_owner {
+                Owners::Leave
+            } else if numeric_owner {
+                Owners::Numeric
+            } else if by_name {
+                Owners::ByName
+            } else {
+                Owners::for_current_user()
+            };
+
+            for skipped in casync::tools::extract(&source.chunks()?, &index, &target, owners)? {
+                eprintln!("skipped socket, or device we may not create: {:?}", skipped);
+            }
+        }
+        Command::Gc {
+            indexes,
+            store,
+            dry_run,
+        } => {
+            let stats = casync::tools::gc(&store, &indexes, dry_run)?;
+            if dry_run {
+                for path in &stats.dead {
+                    println!("{}", path.display());
+                }
+            }
+            eprintln!("{}", stats);
+        }
+        Command::Verify {
+            index,
+            store,
+            archive,
+        } => {
+            let verified = casync::tools::verify(&store, &index, archive)?;
+            for (_chunk, problem) in &verified.problems {
+                println!("{}", problem);
             }
+            eprintln!("{}", verified);
+            if !verified.is_ok() {
+                bail!("{} bad chunks in {}", verified.problems.len(), store);
+            }
+        }
+        Command::Dump {
+            archive,
+            store,
+            cache_dir,
+            format,
+        } => {
+            let source = match store {
+                Some(store) => Some(Source { store, cache_dir }.chunks()?),
+                None => None,
+            };
+            casync::tools::dump(
+                io::stdout().lock(),
+                source.as_ref(),
+                &archive,
+                format.into(),
+            )?;
+        }
+        Command::Cat {
+            index,
+            source,
+            output,
+        } => {
+            let source = source.chunks()?;
+            match output {
+                Some(path) => casync::tools::cat(fs::File::create(path)?, &source, &index)?,
+                None => casync::tools::cat(io::stdout().lock(), &source, &index)?,
+            };
         }
     }
 
@@ -0,0 +1,140 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::format_err;
+use casync_format::ChunkDigest;
+use casync_format::ChunkId;
+use casync_format::format_chunk_id;
+
+/// upstream's default, too
+const DEFAULT_LEVEL: i32 = 3;
+
+/// A local `.castr`, which chunks can be added to.
+pub struct ChunkStore {
+    root: PathBuf,
+    level: i32,
+    digest: ChunkDigest,
+    stats: StoreStats,
+}
+
+/// What has happened to a `ChunkStore`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    /// chunks which weren't already in the store
+    pub new_chunks: u64,
+    /// the uncompressed size of the `new_chunks`
+    pub new_bytes: u64,
+    /// what the `new_chunks` actually take up, after compression
+    pub stored_bytes: u64,
+    /// chunks which were already present, so were skipped
+    pub existing_chunks: u64,
+    /// the uncompressed size of the `existing_chunks`
+    pub existing_bytes: u64,
+}
+
+impl ChunkStore {
+    /// use (and create, if necessary) the store at `root`
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<ChunkStore, Error> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).with_context(|| format_err!("creating store: {:?}", root))?;
+        Ok(ChunkStore {
+            root,
+            level: DEFAULT_LEVEL,
+            // upstream's default
+            digest: ChunkDigest::Sha512_256,
+            stats: StoreStats::default(),
+        })
+    }
+
+    /// the zstd compression level for new chunks
+    pub fn with_level(mut self, level: i32) -> ChunkStore {
+        self.level = level;
+        self
+    }
+
+    /// how the ids of new chunks are calculated
+    pub fn with_digest(mut self, digest: ChunkDigest) -> ChunkStore {
+        self.digest = digest;
+        self
+    }
+
+    pub fn digest(&self) -> ChunkDigest {
+        self.digest
+    }
+
+    /// Add a chunk, by its uncompressed content, if it isn't already present.
+    pub fn insert(&mut self, data: &[u8]) -> Result<ChunkId, Error> {
+        let id = self.digest.id(data);
+        let chunk_path = self.chunk_path(&id);
+
+        if chunk_path.exists() {
+            self.stats.existing(data);
+            return Ok(id);
+        }
+
+        let compressed = zstd::bulk::compress(data, self.level)?;
+
+        fs::create_dir_all(chunk_path.parent().unwrap())?;
+
+        let mut temp = tempfile_fast::PersistableTempFile::new_in(&self.root)
+            .with_context(|| format_err!("creating temporary file inside {:?}", self.root))?;
+        temp.write_all(&compressed)?;
+
+        match temp.persist_noclobber(&chunk_path).map_err(|e| e.error) {
+            Ok(_) => self.stats.stored(data, &compressed),
+            // someone else wrote it in the meantime
+            Err(ref e) if io::ErrorKind::AlreadyExists == e.kind() => self.stats.existing(data),
+            Err(e) => {
+                Err(e).with_context(|| format_err!("storing chunk into: {:?}", chunk_path))?
+            }
+        }
+
+        Ok(id)
+    }
+
+    /// where the chunk with this `id` is, or would be, stored
+    pub fn chunk_path(&self, id: &ChunkId) -> PathBuf {
+        self.root.join(format_chunk_id(id))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn stats(&self) -> &StoreStats {
+        &self.stats
+    }
+}
+
+impl StoreStats {
+    fn stored(&mut self, data: &[u8], compressed: &[u8]) {
+        self.new_chunks += 1;
+        self.new_bytes += data.len() as u64;
+        self.stored_bytes += compressed.len() as u64;
+    }
+
+    fn existing(&mut self, data: &[u8]) {
+        self.existing_chunks += 1;
+        self.existing_bytes += data.len() as u64;
+    }
+}
+
+impl fmt::Display for StoreStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} new chunks ({} bytes, {} compressed), {} already stored ({} bytes)",
+            self.new_chunks,
+            self.new_bytes,
+            self.stored_bytes,
+            self.existing_chunks,
+            self.existing_bytes
+        )
+    }
+}
@@ -0,0 +1,17 @@
+/// The major number of a `dev_t`, as glibc's `gnu_dev_major`.
+pub fn major(dev: u64) -> u64 {
+    ((dev >> 8) & 0xfff) | ((dev >> 32) & 0xfffff000)
+}
+
+/// The minor number of a `dev_t`, as glibc's `gnu_dev_minor`.
+pub fn minor(dev: u64) -> u64 {
+    (dev & 0xff) | ((dev >> 12) & 0xffffff00)
+}
+
+/// A `dev_t` from its major and minor numbers, as glibc's `gnu_dev_makedev`.
+pub fn makedev(major: u64, minor: u64) -> u64 {
+    ((major & 0xfff) << 8)
+        | ((major & 0xfffff000) << 32)
+        | (minor & 0xff)
+        | ((minor & 0xffffff00) << 12)
+}
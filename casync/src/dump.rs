@@ -0,0 +1,179 @@
+use std::fmt::Write as _;
+use std::io::Read;
+use std::io::Write;
+
+use anyhow::Error;
+use casync_format::Entry;
+use casync_format::Goodbye;
+use casync_format::Packet;
+use casync_format::PacketReader;
+use casync_format::StreamMagic;
+use casync_format::Timestamp;
+
+use crate::hex::hex;
+
+/// How `dump` describes each packet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// a line per packet, indented by how many directories are open
+    Text,
+    /// a JSON object per line, for scripts
+    Json,
+}
+
+/// Describe every packet in the `catar` in `from`, even if the archive is otherwise invalid.
+///
+/// Stops at the first packet which can't be read, after describing everything before it.
+pub fn dump<R: Read, W: Write>(from: R, mut into: W, format: DumpFormat) -> Result<(), Error> {
+    // how many directories are open, not counting any which this packet ends
+    let mut depth = 0usize;
+
+    for packet in PacketReader::new(from) {
+        let packet = packet?;
+        let entry = match packet.magic {
+            StreamMagic::Entry => Some(packet.entry()),
+            _ => None,
+        };
+
+        if StreamMagic::Bye == packet.magic {
+            depth = depth.saturating_sub(1);
+        }
+
+        let detail = detail(&packet, entry.as_ref());
+        match format {
+            DumpFormat::Text => {
+                let detail = detail
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value.text()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                writeln!(
+                    into,
+                    "{:>10} {}{:?} ({}) {}",
+                    packet.offset,
+                    "  ".repeat(depth),
+                    packet.magic,
+                    packet.size,
+                    detail
+                )?;
+            }
+            DumpFormat::Json => {
+                let mut line = format!(
+                    "{{\"offset\":{},\"size\":{},\"type\":{},\"depth\":{}",
+                    packet.offset,
+                    packet.size,
+                    json_string(&format!("{:?}", packet.magic)),
+                    depth
+                );
+                for (key, value) in &detail {
+                    write!(line, ",{}:{}", json_string(key), value.json())?;
+                }
+                line.push('}');
+                writeln!(into, "{}", line)?;
+            }
+        }
+
+        if let Some(Ok(entry)) = entry
+            && entry.is_dir()
+        {
+            depth += 1;
+        }
+    }
+    Ok(())
+}
+
+enum Value {
+    Number(u64),
+    Octal(u64),
+    Hex(u64),
+    Time(Timestamp),
+    String(String),
+}
+
+impl Value {
+    fn text(&self) -> String {
+        match self {
+            Value::Number(n) => n.to_string(),
+            Value::Octal(n) => format!("0o{:o}", n),
+            Value::Hex(n) => format!("{:#x}", n),
+            Value::Time(time) => time.to_string(),
+            Value::String(s) => format!("{:?}", s),
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Value::Number(n) | Value::Octal(n) | Value::Hex(n) => n.to_string(),
+            // nanoseconds, as in the archive
+            Value::Time(time) => time.as_nanos().to_string(),
+            Value::String(s) => json_string(s),
+        }
+    }
+}
+
+/// the interesting parts of a packet's payload, in order
+fn detail(
+    packet: &Packet,
+    entry: Option<&Result<Entry, casync_format::Error>>,
+) -> Vec<(&'static str, Value)> {
+    let lossy = |bytes: &[u8]| Value::String(String::from_utf8_lossy(bytes).into_owned());
+
+    if let Some(entry) = entry {
+        return match entry {
+            Ok(entry) => vec![
+                ("mode", Value::Octal(entry.mode)),
+                ("uid", Value::Number(entry.uid)),
+                ("gid", Value::Number(entry.gid)),
+                ("mtime", Value::Time(entry.mtime)),
+                ("flags", Value::Hex(entry.flags)),
+                ("features", Value::Hex(entry.feature_flags.bits())),
+            ],
+            Err(e) => vec![("invalid", Value::String(e.to_string()))],
+        };
+    }
+
+    if let Some(text) = packet.text() {
+        return vec![("text", lossy(text))];
+    }
+
+    if let Some((major, minor)) = packet.device() {
+        return vec![
+            ("major", Value::Number(major)),
+            ("minor", Value::Number(minor)),
+        ];
+    }
+
+    match packet.magic {
+        StreamMagic::Data => vec![("len", Value::Number(packet.size.saturating_sub(16)))],
+        StreamMagic::Xattr => match packet.payload.iter().position(|&b| 0 == b) {
+            Some(nul) => vec![
+                ("name", lossy(&packet.payload[..nul])),
+                ("value", lossy(&packet.payload[nul + 1..])),
+            ],
+            None => vec![("payload", Value::String(hex(&packet.payload)))],
+        },
+        StreamMagic::Bye => match Goodbye::parse(&packet.payload) {
+            Ok(table) => vec![("items", Value::Number(table.items.len() as u64))],
+            Err(e) => vec![("invalid", Value::String(e.to_string()))],
+        },
+        _ => vec![("payload", Value::String(hex(&packet.payload)))],
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if u32::from(c) < 0x20 => {
+                write!(out, "\\u{:04x}", u32::from(c)).expect("writing to a string");
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
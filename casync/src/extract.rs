@@ -0,0 +1,331 @@
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::fchown;
+use std::os::unix::fs::lchown;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::format_err;
+use casync_format::Content;
+use casync_format::Entry;
+use casync_format::Stream;
+use casync_format::Timestamp;
+
+use crate::dev::makedev;
+use crate::names::Names;
+
+/// How to set the owner of what's extracted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Owners {
+    /// leave everything owned by whoever is extracting
+    Leave,
+    /// use the `uid` and `gid` recorded in the archive
+    Numeric,
+    /// look up the recorded `user_name` and `group_name` locally, falling back to the ids
+    ByName,
+}
+
+impl Owners {
+    /// as upstream: `Numeric` when running as root, and `Leave` otherwise,
+    /// as only root can give files away
+    pub fn for_current_user() -> Owners {
+        if is_root() {
+            Owners::Numeric
+        } else {
+            Owners::Leave
+        }
+    }
+}
+
+fn is_root() -> bool {
+    // always succeeds
+    0 == unsafe { libc::geteuid() }
+}
+
+/// Unpack the `catar` in `from` into `target`, which is created if necessary.
+///
+/// Each item's mode, owner and modification time are set once its content
+/// (for a directory, all of its children) has been written.
+///
+/// Hardlinks are recreated as links to the file which was extracted earlier.
+///
+/// Returns the items which were skipped: sockets, which only exist while something
+/// is listening on them, and device nodes, unless we're allowed to create them.
+pub fn extract<R: Read>(from: R, target: &Path, owners: Owners) -> Result<Vec<PathBuf>, Error> {
+    fs::create_dir_all(target).with_context(|| format_err!("creating {:?}", target))?;
+
+    let mut extractor = Extractor {
+        owners,
+        users: Names::users(),
+        groups: Names::groups(),
+        made: target.to_path_buf(),
+        root: is_root(),
+    };
+
+    let mut skipped = Vec::new();
+    let mut stream = Stream::new(from);
+
+    while let Some((path, content)) = stream.next()? {
+        let items: Vec<_> = path.into_iter().collect();
+        let (item, parents) = items.split_last().expect("paths are never empty");
+        let entry = item
+            .entry
+            .as_ref()
+            .ok_or_else(|| format_err!("no entry for item"))?;
+
+        // the first item is the root, which is the target itself
+        let mut dest = target.to_path_buf();
+        for parent in parents.iter().skip(1) {
+            dest.push(checked_name(&parent.name)?);
+        }
+        extractor.make_parents(target, &dest)?;
+
+        if !parents.is_empty() {
+            dest.push(checked_name(&item.name)?);
+        }
+
+        match content {
+            Content::File(mut data) => {
+                remove_non_dir(&dest)?;
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&dest)
+                    .with_context(|| format_err!("creating {:?}", dest))?;
+                io::copy(&mut data, &mut file)?;
+                extractor.finish(&file, entry, &dest)?;
+            }
+            Content::Directory => {
+                make_dir(&dest)?;
+                extractor.finish(&fs::File::open(&dest)?, entry, &dest)?;
+            }
+            Content::Symlink(link) => {
+                remove_non_dir(&dest)?;
+                symlink(OsStr::from_bytes(&link), &dest)
+                    .with_context(|| format_err!("creating symlink {:?}", dest))?;
+                // the mode and time of a symlink aren't interesting
+                if let Some((uid, gid)) = extractor.owner(entry)? {
+                    lchown(&dest, Some(uid), Some(gid))
+                        .with_context(|| format_err!("setting owner of {:?}", dest))?;
+                }
+            }
+            Content::Hardlink(link) => {
+                let original = link_target(target, &link)?;
+                remove_non_dir(&dest)?;
+                // the owner, mode and time are the original's, which are already set
+                fs::hard_link(&original, &dest)
+                    .with_context(|| format_err!("linking {:?} to {:?}", dest, original))?;
+            }
+            Content::Fifo => {
+                remove_non_dir(&dest)?;
+                make_node(&dest, libc::S_IFIFO, 0)?;
+                extractor.finish_node(entry, &dest)?;
+            }
+            Content::Device { major, minor } if extractor.root => {
+                let kind = if entry.is_chr() {
+                    libc::S_IFCHR
+                } else {
+                    libc::S_IFBLK
+                };
+                remove_non_dir(&dest)?;
+                match make_node(&dest, kind, makedev(major, minor)) {
+                    Ok(()) => extractor.finish_node(entry, &dest)?,
+                    // root, but e.g. in a container without `CAP_MKNOD`
+                    Err(e) if io::ErrorKind::PermissionDenied == e.kind() => skipped.push(dest),
+                    Err(e) => {
+                        return Err(e).with_context(|| format_err!("creating device {:?}", dest));
+                    }
+                }
+            }
+            Content::Device { .. } | Content::Socket => skipped.push(dest),
+        }
+    }
+
+    Ok(skipped)
+}
+
+struct Extractor {
+    owners: Owners,
+    users: Names,
+    groups: Names,
+    /// the directory the last item was in, which we know exists
+    made: PathBuf,
+    /// whether we can try to create device nodes
+    root: bool,
+}
+
+impl Extractor {
+    /// create the directories leading to `parent`, if this item isn't in the same one as the last
+    fn make_parents(&mut self, target: &Path, parent: &Path) -> Result<(), Error> {
+        if parent == self.made {
+            return Ok(());
+        }
+
+        let mut dir = target.to_path_buf();
+        for name in parent.strip_prefix(target)?.iter() {
+            dir.push(name);
+            make_dir(&dir)?;
+        }
+
+        self.made = parent.to_path_buf();
+        Ok(())
+    }
+
+    /// set the owner, mode and time from `entry`, once the item is otherwise done
+    fn finish(&self, file: &fs::File, entry: &Entry, path: &Path) -> Result<(), Error> {
+        // before the mode, as changing the owner can clear set-id bits
+        if let Some((uid, gid)) = self.owner(entry)? {
+            fchown(file, Some(uid), Some(gid))
+                .with_context(|| format_err!("setting owner of {:?}", path))?;
+        }
+
+        file.set_permissions(fs::Permissions::from_mode((entry.mode & 0o7777) as u32))
+            .with_context(|| format_err!("setting mode of {:?}", path))?;
+
+        file.set_modified(SystemTime::from(entry.mtime))
+            .with_context(|| format_err!("setting modification time of {:?}", path))?;
+
+        Ok(())
+    }
+
+    /// as `finish`, for fifos and devices, which can't be opened without side effects
+    fn finish_node(&self, entry: &Entry, path: &Path) -> Result<(), Error> {
+        if let Some((uid, gid)) = self.owner(entry)? {
+            lchown(path, Some(uid), Some(gid))
+                .with_context(|| format_err!("setting owner of {:?}", path))?;
+        }
+
+        fs::set_permissions(
+            path,
+            fs::Permissions::from_mode((entry.mode & 0o7777) as u32),
+        )
+        .with_context(|| format_err!("setting mode of {:?}", path))?;
+
+        set_mtime(path, entry.mtime)
+            .with_context(|| format_err!("setting modification time of {:?}", path))?;
+
+        Ok(())
+    }
+
+    fn owner(&self, entry: &Entry) -> Result<Option<(u32, u32)>, Error> {
+        let (uid, gid) = match self.owners {
+            Owners::Leave => return Ok(None),
+            Owners::Numeric => (entry.uid, entry.gid),
+            Owners::ByName => (
+                entry
+                    .user_name
+                    .as_ref()
+                    .and_then(|name| self.users.id(name))
+                    .unwrap_or(entry.uid),
+                entry
+                    .group_name
+                    .as_ref()
+                    .and_then(|name| self.groups.id(name))
+                    .unwrap_or(entry.gid),
+            ),
+        };
+
+        Ok(Some((u32::try_from(uid)?, u32::try_from(gid)?)))
+    }
+}
+
+/// refuse names which would escape from where they're being extracted to
+fn checked_name(name: &[u8]) -> Result<&OsStr, Error> {
+    ensure!(
+        !name.is_empty()
+            && b"." != name
+            && b".." != name
+            && !name.contains(&b'/')
+            && !name.contains(&0),
+        "refusing to extract unsafe name: {:?}",
+        String::from_utf8_lossy(name)
+    );
+    Ok(OsStr::from_bytes(name))
+}
+
+/// where the file a hardlink refers to was extracted to, which must be inside `target`
+fn link_target(target: &Path, link: &[u8]) -> Result<PathBuf, Error> {
+    let mut path = target.to_path_buf();
+    let mut names = link.split(|&b| b'/' == b).peekable();
+    while let Some(name) = names.next() {
+        path.push(checked_name(name)?);
+        let meta = fs::symlink_metadata(&path)
+            .with_context(|| format_err!("finding hardlink target {:?}", path))?;
+
+        // not following symlinks, which could lead anywhere
+        if names.peek().is_some() {
+            ensure!(
+                meta.is_dir(),
+                "hardlink target {:?} isn't a directory",
+                path
+            );
+        } else {
+            ensure!(meta.is_file(), "hardlink target {:?} isn't a file", path);
+        }
+    }
+    Ok(path)
+}
+
+/// create a directory, or accept an existing one; but not a symlink to one
+fn make_dir(path: &Path) -> Result<(), Error> {
+    match fs::create_dir(path) {
+        Ok(()) => Ok(()),
+        Err(ref e) if io::ErrorKind::AlreadyExists == e.kind() => {
+            if !fs::symlink_metadata(path)?.is_dir() {
+                bail!("{:?} already exists, and isn't a directory", path);
+            }
+            Ok(())
+        }
+        Err(e) => Err(e).with_context(|| format_err!("creating directory {:?}", path)),
+    }
+}
+
+/// `mknod`, with only the owner able to touch it until `finish_node`
+fn make_node(path: &Path, kind: libc::mode_t, dev: u64) -> io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    if 0 != unsafe { libc::mknod(path.as_ptr(), kind | 0o600, dev as libc::dev_t) } {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// set the modification time of `path` itself, which std can only do through an open file
+fn set_mtime(path: &Path, mtime: Timestamp) -> io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let times = [
+        libc::timespec {
+            tv_sec: 0,
+            tv_nsec: libc::UTIME_OMIT,
+        },
+        libc::timespec {
+            tv_sec: mtime.secs() as libc::time_t,
+            tv_nsec: mtime.subsec_nanos() as libc::c_long,
+        },
+    ];
+    let flags = libc::AT_SYMLINK_NOFOLLOW;
+    if 0 != unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), flags) } {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// clear the way for a new non-directory, without following any symlink already there
+fn remove_non_dir(path: &Path) -> Result<(), Error> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => bail!("{:?} already exists as a directory", path),
+        Ok(_) => fs::remove_file(path).with_context(|| format_err!("replacing {:?}", path)),
+        Err(ref e) if io::ErrorKind::NotFound == e.kind() => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+
+use anyhow::Error;
+use anyhow::ensure;
+use anyhow::format_err;
+use casync_format::Content;
+use casync_format::Stream;
+use sha2::Digest;
+use sha2::Sha256;
+use tempfile_fast::PersistableTempFile;
+
+/// Who made a commit, when, and why, for `FastExport`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitInfo {
+    /// like `Jo Bloggs <jo@example.com>`; used as the committer, too
+    pub author: String,
+    /// seconds since the epoch; by default, the modification time of the archive's root
+    pub time: Option<i64>,
+    pub message: String,
+}
+
+/// Writes archives as a history for `git fast-import`, one commit per archive.
+///
+/// Each commit follows the previous one, and has exactly the archive's regular files,
+/// symlinks and hardlinks in; git can't store the other types, or empty directories.
+/// Files with the same content are only sent once, even across commits.
+pub struct FastExport<W> {
+    into: W,
+    /// the mark of every blob we've sent, by the sha256 of its content
+    blobs: HashMap<[u8; 32], u64>,
+    last_mark: u64,
+    /// the mark of the previous commit
+    parent: Option<u64>,
+}
+
+impl<W: Write> FastExport<W> {
+    pub fn new(mut into: W) -> Result<FastExport<W>, Error> {
+        writeln!(into, "feature done")?;
+        Ok(FastExport {
+            into,
+            blobs: HashMap::new(),
+            last_mark: 0,
+            parent: None,
+        })
+    }
+
+    /// Add the `catar` in `from` as a commit on `reference`, like `refs/heads/main`.
+    ///
+    /// Each file's content is copied to a temporary file while it's being checked for duplicates.
+    pub fn commit<R: Read>(
+        &mut self,
+        reference: &str,
+        from: R,
+        info: &CommitInfo,
+    ) -> Result<(), Error> {
+        // the blobs have to be sent before the commit which refers to them,
+        // and the root's time is only known at the end
+        let mut changes = Vec::new();
+        // the mark of each file, by its path, for hardlinks to it
+        let mut files: HashMap<Vec<u8>, u64> = HashMap::new();
+        let mut root_time = None;
+
+        let mut stream = Stream::new(from);
+        while let Some((path, content)) = stream.next()? {
+            let items: Vec<_> = path.into_iter().collect();
+            let (item, parents) = items.split_last().expect("paths are never empty");
+            let entry = item
+                .entry
+                .as_ref()
+                .ok_or_else(|| format_err!("no entry for item"))?;
+
+            if parents.is_empty() {
+                root_time = Some(entry.mtime.secs());
+                continue;
+            }
+
+            let names: Vec<&[u8]> = parents
+                .iter()
+                .skip(1)
+                .chain([item])
+                .map(|item| &item.name[..])
+                .collect();
+            let name = names.join(&b'/');
+
+            let executable = 0o100 == (entry.mode & 0o100);
+            let file_mode = if executable { "100755" } else { "100644" };
+
+            let (mode, mark) = match content {
+                Content::File(data) => {
+                    ensure!(entry.is_reg(), "data for non-regular file");
+                    let (hash, len, spooled) = spool(data)?;
+                    let mark = self.blob(hash, len, spooled)?;
+                    files.insert(name.clone(), mark);
+                    (file_mode, mark)
+                }
+                Content::Hardlink(target) => {
+                    let mark = *files.get(&target[..]).ok_or_else(|| {
+                        format_err!(
+                            "hardlink to a file which hasn't been seen: {:?}",
+                            String::from_utf8_lossy(&target)
+                        )
+                    })?;
+                    (file_mode, mark)
+                }
+                Content::Symlink(target) => (
+                    "120000",
+                    self.blob(
+                        Sha256::digest(&target).into(),
+                        target.len() as u64,
+                        &target[..],
+                    )?,
+                ),
+                Content::Directory | Content::Device { .. } | Content::Fifo | Content::Socket => {
+                    continue;
+                }
+            };
+            changes.push(format!("M {} :{} {}", mode, mark, quote(&name)));
+        }
+
+        let time = match (info.time, root_time) {
+            (Some(time), _) => time,
+            (None, Some(time)) => i64::try_from(time)?,
+            (None, None) => 0,
+        };
+
+        self.last_mark += 1;
+        writeln!(self.into, "commit {}", reference)?;
+        writeln!(self.into, "mark :{}", self.last_mark)?;
+        writeln!(self.into, "author {} {} +0000", info.author, time)?;
+        writeln!(self.into, "committer {} {} +0000", info.author, time)?;
+        writeln!(self.into, "data {}", info.message.len())?;
+        writeln!(self.into, "{}", info.message)?;
+        if let Some(parent) = self.parent {
+            writeln!(self.into, "from :{}", parent)?;
+        }
+        writeln!(self.into, "deleteall")?;
+        for change in changes {
+            writeln!(self.into, "{}", change)?;
+        }
+        writeln!(self.into)?;
+
+        self.parent = Some(self.last_mark);
+        Ok(())
+    }
+
+    /// end the stream, and return the underlying writer, which hasn't been flushed
+    pub fn finish(mut self) -> Result<W, Error> {
+        writeln!(self.into, "done")?;
+        Ok(self.into)
+    }
+
+    /// send the `len` bytes of `content`, whose sha256 is `hash`, unless we already have,
+    /// returning its mark
+    fn blob<R: Read>(&mut self, hash: [u8; 32], len: u64, content: R) -> Result<u64, Error> {
+        if let Some(mark) = self.blobs.get(&hash) {
+            return Ok(*mark);
+        }
+
+        self.last_mark += 1;
+        writeln!(self.into, "blob")?;
+        writeln!(self.into, "mark :{}", self.last_mark)?;
+        writeln!(self.into, "data {}", len)?;
+        let sent = io::copy(&mut content.take(len), &mut self.into)?;
+        ensure!(sent == len, "blob ended early: {} of {} bytes", sent, len);
+        writeln!(self.into)?;
+
+        self.blobs.insert(hash, self.last_mark);
+        Ok(self.last_mark)
+    }
+}
+
+/// copy `data` to a temporary file, returning its sha256, its length, and the file, rewound
+fn spool<R: Read>(mut data: R) -> Result<([u8; 32], u64, PersistableTempFile), Error> {
+    let mut file = PersistableTempFile::new_in(env::temp_dir())?;
+    let mut hasher = Sha256::new();
+    let mut len = 0;
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let read = match data.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if io::ErrorKind::Interrupted == e.kind() => continue,
+            Err(e) => return Err(e.into()),
+        };
+        hasher.update(&buf[..read]);
+        file.write_all(&buf[..read])?;
+        len += read as u64;
+    }
+    file.rewind()?;
+    Ok((hasher.finalize().into(), len, file))
+}
+
+/// as git does for anything but plain ascii: in quotes, with C-style escapes
+fn quote(path: &[u8]) -> String {
+    if path
+        .iter()
+        .all(|&b| b.is_ascii() && !b.is_ascii_control() && b'"' != b && b'\\' != b)
+    {
+        return String::from_utf8_lossy(path).into_owned();
+    }
+
+    let mut out = String::from("\"");
+    for &b in path {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b if b.is_ascii_control() || !b.is_ascii() => out.push_str(&format!("\\{:03o}", b)),
+            b => out.push(char::from(b)),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,61 @@
+use std::future::Future;
+use std::io::Write;
+use std::pin::pin;
+
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::format_err;
+use casync_format::Chunk;
+use futures_util::Stream;
+use futures_util::StreamExt;
+use futures_util::stream;
+
+/// Fetches compressed chunks, like `casync_format`'s fetchers do, but asynchronously.
+///
+/// The chunk's path in a store is its `format_id()`, e.g. `abcd/abcdefg012[..]30.cacnk`.
+pub trait AsyncFetcher {
+    fn fetch(&self, chunk: &Chunk) -> impl Future<Output = Result<Vec<u8>, Error>>;
+
+    /// the decompressed, and checked, content of the chunk; for fetchers which check anyway
+    fn fetch_checked(&self, chunk: &Chunk) -> impl Future<Output = Result<Vec<u8>, Error>> {
+        let chunk = *chunk;
+        async move {
+            let compressed = self.fetch(&chunk).await?;
+            Ok(chunk.decompress(&compressed)?)
+        }
+    }
+}
+
+/// Fetch, decompress, and check `chunks`, with up to `parallelism` of them in flight
+/// at once, yielding their content in index order.
+pub fn fetch_chunks<F: AsyncFetcher>(
+    fetcher: &F,
+    chunks: Vec<Chunk>,
+    parallelism: usize,
+) -> impl Stream<Item = Result<Vec<u8>, Error>> + '_ {
+    stream::iter(chunks)
+        .map(move |chunk| async move {
+            fetcher
+                .fetch_checked(&chunk)
+                .await
+                .with_context(|| format_err!("loading chunk {}", chunk.format_id()))
+        })
+        .buffered(parallelism.max(1))
+}
+
+/// write out the whole stream `chunks` describe, returning its length
+pub async fn copy_chunks<F: AsyncFetcher, W: Write>(
+    fetcher: &F,
+    chunks: Vec<Chunk>,
+    parallelism: usize,
+    mut into: W,
+) -> Result<u64, Error> {
+    let mut chunks = pin!(fetch_chunks(fetcher, chunks, parallelism));
+    let mut written = 0;
+    while let Some(data) = chunks.next().await {
+        let data = data?;
+        into.write_all(&data)?;
+        written += data.len() as u64;
+    }
+    Ok(written)
+}
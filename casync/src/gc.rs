@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::format_err;
+use casync_format::ChunkId;
+use casync_format::format_chunk_id;
+use casync_format::parse_chunk_id;
+
+/// What `gc` found in a store.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// chunks which are referenced, so were kept
+    pub live_chunks: u64,
+    /// the chunks which nothing references, which were deleted, unless it was a dry run
+    pub dead: Vec<PathBuf>,
+    /// the size of the `dead` chunks on disk
+    pub reclaimed_bytes: u64,
+    pub dry_run: bool,
+}
+
+/// Delete every chunk in the `.castr` at `root` which isn't in `live`, or just find them,
+/// for a `dry_run`.
+///
+/// Only files named as `ChunkStore` names chunks are considered; anything else is left alone.
+/// Chunks being added at the same time, for an index which isn't finished yet, will be lost.
+pub fn gc<P: AsRef<Path>>(
+    root: P,
+    live: &HashSet<ChunkId>,
+    dry_run: bool,
+) -> Result<GcStats, Error> {
+    let root = root.as_ref();
+    let mut stats = GcStats {
+        dry_run,
+        ..GcStats::default()
+    };
+
+    for prefix in sorted_dir(root)? {
+        let name = prefix.file_name().and_then(|name| name.to_str());
+        if !name.is_some_and(|name| 4 == name.len() && name.bytes().all(|b| b.is_ascii_hexdigit()))
+            || !prefix.is_dir()
+        {
+            continue;
+        }
+
+        let mut emptied = true;
+        for chunk in sorted_dir(&prefix)? {
+            let id = chunk
+                .strip_prefix(root)
+                .ok()
+                .and_then(|relative| relative.to_str())
+                .and_then(parse_chunk_id)
+                // not just something which looks like a chunk in the wrong place
+                .filter(|id| root.join(format_chunk_id(id)) == chunk);
+
+            match id {
+                Some(id) if live.contains(&id) => {
+                    stats.live_chunks += 1;
+                    emptied = false;
+                }
+                Some(_) => {
+                    stats.reclaimed_bytes += fs::symlink_metadata(&chunk)?.len();
+                    if !dry_run {
+                        fs::remove_file(&chunk)
+                            .with_context(|| format_err!("deleting {:?}", chunk))?;
+                    }
+                    stats.dead.push(chunk);
+                }
+                None => emptied = false,
+            }
+        }
+
+        if emptied && !dry_run {
+            // someone may have just added a chunk, which is fine
+            let _ = fs::remove_dir(&prefix);
+        }
+    }
+
+    Ok(stats)
+}
+
+fn sorted_dir(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = fs::read_dir(dir)
+        .with_context(|| format_err!("listing {:?}", dir))?
+        .map(|dirent| dirent.map(|dirent| dirent.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} unreferenced chunks ({} bytes), kept {}",
+            if self.dry_run {
+                "would delete"
+            } else {
+                "deleted"
+            },
+            self.dead.len(),
+            self.reclaimed_bytes,
+            self.live_chunks
+        )
+    }
+}
@@ -0,0 +1,4 @@
+/// lowercase, two digits per byte, as chunk ids and digests are written
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
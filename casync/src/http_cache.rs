@@ -8,8 +8,12 @@ use anyhow::Context;
 use anyhow::Error;
 use anyhow::bail;
 use anyhow::format_err;
+use casync_format::Chunk;
 use reqwest::Client;
 use reqwest::IntoUrl;
+use reqwest::Url;
+
+use crate::fetch::AsyncFetcher;
 
 pub struct HttpCache<'c> {
     client: &'c Client,
@@ -24,18 +28,41 @@ impl<'c> HttpCache<'c> {
         })
     }
 
-    pub async fn load<U: IntoUrl>(&self, castr: U, cacnk: &str) -> Result<Vec<u8>, Error> {
+    /// fetch a chunk, from the local store if it's there and valid, or from the remote `castr`
+    pub async fn load<U: IntoUrl>(&self, castr: U, chunk: &Chunk) -> Result<Vec<u8>, Error> {
+        Ok(self.load_both(castr, chunk).await?.0)
+    }
+
+    /// as `load`, but the content of the chunk, which has already been decompressed and checked
+    pub async fn load_checked<U: IntoUrl>(
+        &self,
+        castr: U,
+        chunk: &Chunk,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self.load_both(castr, chunk).await?.1)
+    }
+
+    /// the compressed chunk, and its content
+    async fn load_both<U: IntoUrl>(
+        &self,
+        castr: U,
+        chunk: &Chunk,
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let cacnk = chunk.format_id();
         let mut chunk_path = self.local_store.to_path_buf();
-        chunk_path.push(cacnk);
+        chunk_path.push(&cacnk);
 
         match fs::read(&chunk_path) {
-            Ok(v) => return Ok(v),
+            Ok(v) => match chunk.decompress(&v) {
+                Ok(data) => return Ok((v, data)),
+                Err(_) => self.quarantine(&chunk_path)?,
+            },
             Err(ref e) if io::ErrorKind::NotFound == e.kind() => (),
             Err(e) => Err(e)?,
         }
 
         let castr = castr.into_url()?;
-        let cacnk = castr.join(cacnk)?;
+        let cacnk = castr.join(&cacnk)?;
 
         fs::create_dir_all(chunk_path.parent().unwrap())?;
 
@@ -50,6 +77,11 @@ impl<'c> HttpCache<'c> {
         // TODO: chunks() to file, or read.await
         let buf = resp.bytes().await?.to_vec();
 
+        // don't let a truncated, or otherwise broken, download into the cache
+        let data = chunk
+            .decompress(&buf)
+            .with_context(|| format_err!("verifying downloaded chunk\nurl: {}", cacnk))?;
+
         let mut temp =
             tempfile_fast::PersistableTempFile::new_in(&self.local_store).with_context(|| {
                 format_err!("creating temporary directory inside {:?}", self.local_store)
@@ -63,10 +95,53 @@ impl<'c> HttpCache<'c> {
                 .with_context(|| format_err!("storing downloaded chunk into: {:?}", chunk_path))?,
         }
 
-        Ok(buf)
+        Ok((buf, data))
+    }
+
+    /// move a cached chunk which failed verification out of the way, for inspection
+    fn quarantine(&self, chunk_path: &Path) -> Result<(), Error> {
+        let dir = self.quarantine_dir();
+        fs::create_dir_all(&dir)?;
+        let dest = dir.join(chunk_path.file_name().expect("chunk paths have names"));
+        fs::rename(chunk_path, &dest)
+            .with_context(|| format_err!("quarantining {:?} into {:?}", chunk_path, dest))
+    }
+
+    /// where cached chunks which failed verification are moved to
+    pub fn quarantine_dir(&self) -> PathBuf {
+        self.local_store.join("quarantine")
     }
 
     pub fn local_store(&self) -> &Path {
         &self.local_store
     }
+
+    /// the remote `.castr` at `castr`, as an `AsyncFetcher`
+    pub fn store<U: IntoUrl>(&self, castr: U) -> Result<HttpStore<'_, 'c>, Error> {
+        let mut castr = castr.into_url()?;
+
+        // so chunk paths are joined onto the store, instead of replacing its last component
+        if !castr.path().ends_with('/') {
+            let path = format!("{}/", castr.path());
+            castr.set_path(&path);
+        }
+
+        Ok(HttpStore { cache: self, castr })
+    }
+}
+
+/// A remote `.castr`, which is fetched from through an `HttpCache`.
+pub struct HttpStore<'h, 'c> {
+    cache: &'h HttpCache<'c>,
+    castr: Url,
+}
+
+impl AsyncFetcher for HttpStore<'_, '_> {
+    fn fetch(&self, chunk: &Chunk) -> impl Future<Output = Result<Vec<u8>, Error>> {
+        self.cache.load(self.castr.clone(), chunk)
+    }
+
+    fn fetch_checked(&self, chunk: &Chunk) -> impl Future<Output = Result<Vec<u8>, Error>> {
+        self.cache.load_checked(self.castr.clone(), chunk)
+    }
 }
@@ -1,4 +1,42 @@
+mod chunk_store;
+mod dev;
+mod dump;
+mod extract;
+mod fast_export;
+mod fetch;
+mod gc;
+mod hex;
 mod http_cache;
+mod make;
+mod mtree;
+mod names;
+mod remote;
 pub mod tools;
+mod verify;
 
+pub use chunk_store::ChunkStore;
+pub use chunk_store::StoreStats;
+pub use dev::major;
+pub use dev::makedev;
+pub use dev::minor;
+pub use dump::DumpFormat;
+pub use dump::dump;
+pub use extract::Owners;
+pub use extract::extract;
+pub use fast_export::CommitInfo;
+pub use fast_export::FastExport;
+pub use fetch::AsyncFetcher;
+pub use fetch::copy_chunks;
+pub use fetch::fetch_chunks;
+pub use gc::GcStats;
+pub use gc::gc;
 pub use http_cache::HttpCache;
+pub use http_cache::HttpStore;
+pub use make::Hardlinks;
+pub use make::make;
+pub use mtree::mtree;
+pub use remote::ChunkSource;
+pub use remote::load_index;
+pub use verify::Verified;
+pub use verify::check_archive;
+pub use verify::verify;
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::format_err;
+use casync_format::CatarWriter;
+use casync_format::ChunkSize;
+use casync_format::Chunker;
+use casync_format::Entry;
+use casync_format::FeatureFlags;
+use casync_format::IndexKind;
+use casync_format::IndexWriter;
+use casync_format::Timestamp;
+
+use crate::chunk_store::ChunkStore;
+use crate::dev::major;
+use crate::dev::minor;
+use crate::names::Names;
+
+/// what we record
+const FEATURE_FLAGS: FeatureFlags = FeatureFlags::WITH_32BIT_UIDS
+    .union(FeatureFlags::WITH_USER_NAMES)
+    .union(FeatureFlags::WITH_NSEC_TIME)
+    .union(FeatureFlags::WITH_PERMISSIONS)
+    .union(FeatureFlags::WITH_SYMLINKS)
+    .union(FeatureFlags::WITH_DEVICE_NODES)
+    .union(FeatureFlags::WITH_FIFOS)
+    .union(FeatureFlags::WITH_SOCKETS);
+
+/// What `make` does with a file which has several names in the source.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hardlinks {
+    /// archive each name as a separate file, as upstream does; the chunks are shared anyway
+    Copy,
+    /// archive the file once, then a `Hardlink` packet for each other name,
+    /// which only we can read
+    Link,
+}
+
+/// Archive `source` into `store`, and write the index describing it into `index`.
+///
+/// For a `Catar`, `source` is a directory, which is serialised first.
+/// For a `Blob`, the content of `source`, e.g. a disk image, is chunked directly.
+/// Chunk ids are calculated with the `store`'s digest.
+pub fn make<W: Write>(
+    store: &mut ChunkStore,
+    index: W,
+    kind: IndexKind,
+    source: &Path,
+    sizes: ChunkSize,
+    hardlinks: Hardlinks,
+) -> Result<W, Error> {
+    let feature_flags = match kind {
+        IndexKind::Catar => FEATURE_FLAGS | store.digest().flags(),
+        IndexKind::Blob => store.digest().flags(),
+    };
+
+    let mut out = ChunkingWriter {
+        chunker: Chunker::new(sizes),
+        chunk: Vec::new(),
+        store,
+        index: IndexWriter::new(index, feature_flags, sizes)?,
+    };
+
+    match kind {
+        IndexKind::Catar => {
+            let meta = fs::metadata(source).with_context(|| format_err!("reading {:?}", source))?;
+            ensure!(meta.is_dir(), "a .caidx can only be made of a directory");
+            let users = Names::users();
+            let groups = Names::groups();
+            let root = entry(&meta, &users, &groups)?;
+            let mut encoder = Encoder {
+                out: CatarWriter::new(&mut out, &root)?,
+                users,
+                groups,
+                root: source.to_path_buf(),
+                hardlinks,
+                links: HashMap::new(),
+            };
+            encoder.directory(source)?;
+            encoder.out.finish()?;
+        }
+        IndexKind::Blob => {
+            let mut file =
+                fs::File::open(source).with_context(|| format_err!("opening {:?}", source))?;
+            io::copy(&mut file, &mut out)?;
+        }
+    }
+
+    out.finish()
+}
+
+/// splits what's written into chunks, and stores them
+struct ChunkingWriter<'s, W> {
+    chunker: Chunker,
+    chunk: Vec<u8>,
+    store: &'s mut ChunkStore,
+    index: IndexWriter<W>,
+}
+
+impl<W: Write> ChunkingWriter<'_, W> {
+    fn end_chunk(&mut self) -> Result<(), Error> {
+        let id = self.store.insert(&self.chunk)?;
+        self.index
+            .push(self.index.len() + self.chunk.len() as u64, &id)?;
+        self.chunk.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<W, Error> {
+        if !self.chunk.is_empty() {
+            self.end_chunk()?;
+        }
+        Ok(self.index.finish()?)
+    }
+}
+
+impl<W: Write> Write for ChunkingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(used) = self.chunker.scan(rest) {
+            self.chunk.extend_from_slice(&rest[..used]);
+            self.end_chunk().map_err(io::Error::other)?;
+            rest = &rest[used..];
+        }
+        self.chunk.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// walks a directory tree, and feeds it to a `CatarWriter`
+struct Encoder<W> {
+    out: CatarWriter<W>,
+    users: Names,
+    groups: Names,
+    root: PathBuf,
+    hardlinks: Hardlinks,
+    /// the first path we saw for each `(dev, ino)` with several links
+    links: HashMap<(u64, u64), Vec<u8>>,
+}
+
+impl<W: Write> Encoder<W> {
+    /// add the children of the directory at `path`
+    fn directory(&mut self, path: &Path) -> Result<(), Error> {
+        let mut names = Vec::new();
+        for dirent in fs::read_dir(path).with_context(|| format_err!("listing {:?}", path))? {
+            names.push(dirent?.file_name());
+        }
+
+        // upstream writes children in byte order
+        names.sort_by(|left, right| left.as_bytes().cmp(right.as_bytes()));
+
+        for name in names {
+            let child = path.join(&name);
+            let meta =
+                fs::symlink_metadata(&child).with_context(|| format_err!("reading {:?}", child))?;
+            self.item(name.as_bytes(), &child, &meta)
+                .with_context(|| format_err!("archiving {:?}", child))?;
+        }
+
+        Ok(())
+    }
+
+    fn item(&mut self, name: &[u8], path: &Path, meta: &fs::Metadata) -> Result<(), Error> {
+        let entry = entry(meta, &self.users, &self.groups)?;
+        let file_type = meta.file_type();
+        if file_type.is_dir() {
+            self.out.begin_dir(name, &entry)?;
+            self.directory(path)?;
+            self.out.end_dir()?;
+        } else if file_type.is_file() {
+            if Hardlinks::Link == self.hardlinks && meta.nlink() > 1 {
+                let inode = (meta.dev(), meta.ino());
+                if let Some(target) = self.links.get(&inode) {
+                    self.out.add_hardlink(name, &entry, target)?;
+                    return Ok(());
+                }
+                let relative = path.strip_prefix(&self.root)?;
+                self.links
+                    .insert(inode, relative.as_os_str().as_bytes().to_vec());
+            }
+            let file = fs::File::open(path)?;
+            self.out.add_file_with_len(name, &entry, meta.len(), file)?;
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(path)?;
+            self.out
+                .add_symlink(name, &entry, target.as_os_str().as_bytes())?;
+        } else if file_type.is_block_device() || file_type.is_char_device() {
+            self.out
+                .add_device(name, &entry, major(meta.rdev()), minor(meta.rdev()))?;
+        } else if file_type.is_fifo() || file_type.is_socket() {
+            self.out.add_special(name, &entry)?;
+        } else {
+            bail!("unsupported file type")
+        }
+        Ok(())
+    }
+}
+
+fn entry(meta: &fs::Metadata, users: &Names, groups: &Names) -> Result<Entry, Error> {
+    Ok(Entry {
+        feature_flags: FEATURE_FLAGS,
+        mode: u64::from(meta.mode()),
+        uid: u64::from(meta.uid()),
+        gid: u64::from(meta.gid()),
+        mtime: Timestamp::try_from(meta.modified()?)?,
+        user_name: users.name(u64::from(meta.uid())).map(|n| n.into()),
+        group_name: groups.name(u64::from(meta.gid())).map(|n| n.into()),
+        // chattr flags, xattrs, etc., which we don't collect
+        ..Entry::default()
+    })
+}
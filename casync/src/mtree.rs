@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use anyhow::Error;
+use anyhow::format_err;
+use casync_format::Content;
+use casync_format::FeatureFlags;
+use casync_format::Stream;
+use casync_format::Timestamp;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::hex::hex;
+
+/// Describe every item in the `catar` in `from` as an mtree spec, like `casync mtree`.
+///
+/// Each line is a full path, like `./etc/passwd`, so the output can be checked
+/// against a directory with `mtree -f`. Each directory comes before its content,
+/// starting with `.`, so the lines are held in memory until the root is finished.
+pub fn mtree<R: Read, W: Write>(from: R, mut into: W) -> Result<(), Error> {
+    // the size and digest of each file, by path from the root, for any hardlinks to it
+    let mut files: HashMap<Vec<u8>, (u64, String)> = HashMap::new();
+    // the lines of the content of each open directory; `Stream` only gives us
+    // a directory after everything in it
+    let mut open: Vec<Vec<String>> = Vec::new();
+
+    let mut stream = Stream::new(from);
+    while let Some((path, content)) = stream.next()? {
+        let items: Vec<_> = path.into_iter().collect();
+        let (item, parents) = items.split_last().expect("paths are never empty");
+        let entry = item
+            .entry
+            .as_ref()
+            .ok_or_else(|| format_err!("no entry for item"))?;
+        let names: Vec<&[u8]> = items.iter().map(|item| &item.name[..]).collect();
+
+        let mut line = escape(&names.join(&b'/'));
+        let kind = match &content {
+            Content::File(_) | Content::Hardlink(_) => "file",
+            Content::Directory => "dir",
+            Content::Symlink(_) => "link",
+            Content::Device { .. } if entry.is_chr() => "char",
+            Content::Device { .. } => "block",
+            Content::Fifo => "fifo",
+            Content::Socket => "socket",
+        };
+        write!(line, " type={}", kind)?;
+
+        // always 0777 on linux, and can't be changed
+        if !entry.is_lnk() {
+            write!(line, " mode={:o}", entry.mode & 0o7777)?;
+        }
+
+        let flags = entry.feature_flags;
+        if flags.intersects(FeatureFlags::WITH_16BIT_UIDS | FeatureFlags::WITH_32BIT_UIDS) {
+            write!(line, " uid={} gid={}", entry.uid, entry.gid)?;
+        }
+        if let Some(user) = &entry.user_name {
+            write!(line, " uname={}", escape(user))?;
+        }
+        if let Some(group) = &entry.group_name {
+            write!(line, " gname={}", escape(group))?;
+        }
+
+        let file = match content {
+            Content::File(mut data) => {
+                let mut hasher = HashingWriter(Sha256::new());
+                let size = io::copy(&mut data, &mut hasher)?;
+                Some((size, hex(&hasher.0.finalize())))
+            }
+            Content::Hardlink(target) => {
+                Some(files.get(&target[..]).cloned().ok_or_else(|| {
+                    format_err!(
+                        "hardlink to a file which hasn't been seen: {:?}",
+                        String::from_utf8_lossy(&target)
+                    )
+                })?)
+            }
+            Content::Symlink(target) => {
+                write!(line, " link={}", escape(&target))?;
+                None
+            }
+            Content::Device { major, minor } => {
+                write!(line, " device=linux,{},{}", major, minor)?;
+                None
+            }
+            Content::Directory | Content::Fifo | Content::Socket => None,
+        };
+
+        if let Some((size, _)) = &file {
+            write!(line, " size={}", size)?;
+        }
+        if Timestamp::granularity(flags).is_some() {
+            write!(line, " time={}", entry.mtime)?;
+        }
+        if let Some((_, digest)) = &file {
+            write!(line, " sha256digest={}", digest)?;
+        }
+
+        let depth = parents.len();
+        if entry.is_dir() {
+            open.resize_with(open.len().max(depth + 1), Vec::new);
+            let mut lines = vec![line];
+            lines.extend(open.pop().expect("just made"));
+            match open.last_mut() {
+                Some(parent) => parent.extend(lines),
+                None => {
+                    for line in lines {
+                        writeln!(into, "{}", line)?;
+                    }
+                }
+            }
+        } else if let Some(parent) = depth.checked_sub(1) {
+            open.resize_with(open.len().max(depth), Vec::new);
+            open[parent].push(line);
+        } else {
+            // an archive of a single file
+            writeln!(into, "{}", line)?;
+        }
+
+        if let Some(file) = file {
+            // as hardlinks name their targets: without the root's `.`
+            let relative: Vec<&[u8]> = parents
+                .iter()
+                .skip(1)
+                .map(|parent| &parent.name[..])
+                .chain([&item.name[..]])
+                .collect();
+            files.insert(relative.join(&b'/'), file);
+        }
+    }
+    Ok(())
+}
+
+/// as `mtree` and `vis` do: anything which isn't printable ascii, or is a space,
+/// `#` or `\`, is written as a backslash and three octal digits
+fn escape(name: &[u8]) -> String {
+    let mut out = String::with_capacity(name.len());
+    for &b in name {
+        if b.is_ascii_graphic() && b'\\' != b && b'#' != b {
+            out.push(char::from(b));
+        } else {
+            write!(out, "\\{:03o}", b).expect("writing to a string");
+        }
+    }
+    out
+}
+
+struct HashingWriter(Sha256);
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use std::fs;
+
+/// The local user or group names, from `/etc/passwd` or `/etc/group`.
+///
+/// Users only known to other NSS sources (e.g. LDAP) are missed.
+pub(crate) struct Names {
+    by_id: HashMap<u64, Box<[u8]>>,
+    by_name: HashMap<Box<[u8]>, u64>,
+}
+
+impl Names {
+    pub(crate) fn users() -> Names {
+        Names::load("/etc/passwd")
+    }
+
+    pub(crate) fn groups() -> Names {
+        Names::load("/etc/group")
+    }
+
+    /// a missing or unreadable file just means there are no names
+    fn load(path: &str) -> Names {
+        let mut by_id = HashMap::new();
+        let mut by_name = HashMap::new();
+        for line in fs::read(path).unwrap_or_default().split(|&b| b'\n' == b) {
+            // name:password:id:...
+            let mut fields = line.split(|&b| b':' == b);
+            let (Some(name), Some(_), Some(id)) = (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let Some(id) = std::str::from_utf8(id).ok().and_then(|id| id.parse().ok()) else {
+                continue;
+            };
+            if name.is_empty() || name.starts_with(b"#") {
+                continue;
+            }
+
+            // the first entry wins, as with getpwuid and getpwnam
+            by_id.entry(id).or_insert_with(|| name.into());
+            by_name.entry(name.into()).or_insert(id);
+        }
+        Names { by_id, by_name }
+    }
+
+    pub(crate) fn name(&self, id: u64) -> Option<&[u8]> {
+        self.by_id.get(&id).map(|name| &name[..])
+    }
+
+    pub(crate) fn id(&self, name: &[u8]) -> Option<u64> {
+        self.by_name.get(name).copied()
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::bail;
+use anyhow::format_err;
+use casync_format::Chunk;
+use casync_format::ChunkSize;
+use casync_format::FlatReader;
+use casync_format::read_index;
+use futures_util::StreamExt;
+use reqwest::Client;
+use reqwest::Url;
+
+use crate::fetch::fetch_chunks;
+use crate::http_cache::HttpCache;
+
+/// how many chunks to download at once, by default
+const PARALLELISM: usize = 8;
+
+/// A `.castr` to read chunks from: a local directory, or a `http://` or `https://` url,
+/// whose chunks are downloaded into a local cache.
+#[derive(Clone, Debug)]
+pub enum ChunkSource {
+    Local(PathBuf),
+    Http {
+        castr: Url,
+        cache_dir: PathBuf,
+        parallelism: usize,
+    },
+}
+
+impl ChunkSource {
+    /// `castr` is a path or a url; `cache_dir` is only used for urls
+    pub fn new<P: AsRef<Path>>(castr: &str, cache_dir: P) -> Result<ChunkSource, Error> {
+        Ok(if is_url(castr) {
+            ChunkSource::Http {
+                castr: Url::parse(castr).with_context(|| format_err!("parsing url {}", castr))?,
+                cache_dir: cache_dir.as_ref().to_path_buf(),
+                parallelism: PARALLELISM,
+            }
+        } else {
+            ChunkSource::Local(PathBuf::from(castr))
+        })
+    }
+
+    /// download up to `parallelism` chunks at once; ignored for local stores
+    pub fn with_parallelism(mut self, parallelism: usize) -> ChunkSource {
+        if let ChunkSource::Http {
+            parallelism: ours, ..
+        } = &mut self
+        {
+            *ours = parallelism.max(1);
+        }
+        self
+    }
+
+    /// the stream `chunks` describe, which are fetched, and checked, as it is read
+    pub fn open(&self, chunks: Vec<Chunk>) -> Box<dyn Read + Send> {
+        match self {
+            ChunkSource::Local(root) => {
+                let root = root.clone();
+                Box::new(casync_format::chunks::from_chunks(
+                    chunks,
+                    move |cacnk: &str| fs::read(root.join(cacnk)),
+                ))
+            }
+            ChunkSource::Http {
+                castr,
+                cache_dir,
+                parallelism,
+            } => Box::new(FlatReader::new(download(
+                castr.clone(),
+                cache_dir.clone(),
+                chunks,
+                *parallelism,
+            ))),
+        }
+    }
+
+    /// the stream the index at `index`, a path or a url, describes
+    pub fn open_index(&self, index: &str) -> Result<Box<dyn Read + Send>, Error> {
+        let (_sizes, chunks) = load_index(index)?;
+        Ok(self.open(chunks))
+    }
+}
+
+/// read an index from a path, or a `http://` or `https://` url
+pub fn load_index(index: &str) -> Result<(ChunkSize, Vec<Chunk>), Error> {
+    let data = if is_url(index) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            let resp = Client::new().get(index).send().await?;
+            if !resp.status().is_success() {
+                bail!("couldn't download index: {}\nurl: {}", resp.status(), index);
+            }
+            Ok(resp.bytes().await?.to_vec())
+        })?
+    } else {
+        fs::read(index).with_context(|| format_err!("reading index {}", index))?
+    };
+    read_index(io::Cursor::new(data)).with_context(|| format_err!("parsing index {}", index))
+}
+
+pub(crate) fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// fetch `chunks` on a background thread, which runs ahead of the reader
+/// by at most `parallelism` chunks
+fn download(
+    castr: Url,
+    cache_dir: PathBuf,
+    chunks: Vec<Chunk>,
+    parallelism: usize,
+) -> mpsc::IntoIter<io::Result<Vec<u8>>> {
+    let (send, recv) = mpsc::sync_channel(parallelism);
+
+    thread::spawn(move || {
+        let fetched = (|| -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            let client = Client::new();
+            let cache = HttpCache::new(&client, &cache_dir)?;
+            let store = cache.store(castr)?;
+
+            // the fetched chunks arrive in order, so can be checked against the index
+            let mut expected = chunks.clone().into_iter();
+            let mut start = 0;
+            runtime.block_on(async {
+                let mut chunks = pin!(fetch_chunks(&store, chunks, parallelism));
+                while let Some(data) = chunks.next().await {
+                    let data = data.and_then(|data| {
+                        let chunk = expected.next().expect("a chunk per chunk");
+                        chunk.check_len(start, &data)?;
+                        start = chunk.offset;
+                        Ok(data)
+                    });
+                    // nothing after an error is wanted
+                    let failed = data.is_err();
+                    // blocks the downloads while the reader is behind; fails if it has gone away
+                    if send.send(data.map_err(io::Error::other)).is_err() || failed {
+                        break;
+                    }
+                }
+            });
+            Ok(())
+        })();
+
+        if let Err(e) = fetched {
+            // the reader may have gone away, which is fine
+            let _ = send.send(Err(io::Error::other(e)));
+        }
+    });
+
+    recv.into_iter()
+}
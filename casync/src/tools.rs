@@ -1,75 +1,176 @@
+use std::collections::HashSet;
 use std::fs;
 use std::io;
 use std::io::Read;
 use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
 
 use anyhow::Context;
 use anyhow::Error;
-use anyhow::bail;
 use anyhow::ensure;
 use anyhow::format_err;
 
-use casync_format::Stream;
-use casync_format::chunks::from_paths;
+use casync_format::ChunkDigest;
+use casync_format::ChunkSize;
+use casync_format::IndexKind;
 
-pub fn fast_export<W: Write>(mut into: W, castr: &str, caidx: &str) -> Result<(), Error> {
-    let mut stream = Stream::new(from_paths(caidx, castr, move |path: &str| fs::read(path))?);
+use crate::chunk_store::ChunkStore;
+use crate::chunk_store::StoreStats;
+use crate::dump::DumpFormat;
+use crate::extract::Owners;
+use crate::fast_export::CommitInfo;
+use crate::fast_export::FastExport;
+use crate::gc::GcStats;
+use crate::make::Hardlinks;
+use crate::remote::ChunkSource;
+use crate::remote::is_url;
+use crate::remote::load_index;
+use crate::verify::Verified;
 
-    while let Some(path_content) = stream
-        .next()
-        .with_context(|| format_err!("reading stream of index {}", caidx))?
-    {
-        let (path, content) = path_content;
-        let last = path.end().clone();
-        let names: Vec<Box<[u8]>> = path.into_iter().map(|item| item.name).collect();
-
-        let last_entry = match last.entry {
-            Some(x) => x,
-            None => bail!("no entry for item"),
+/// Write the archives `indexes` describe as a history for `git fast-import`,
+/// one commit for each, on `{ref_prefix}{n}`.
+///
+/// With an empty `message`, each commit's is the location of its index.
+pub fn fast_export<W: Write>(
+    into: W,
+    castr: &ChunkSource,
+    indexes: &[String],
+    ref_prefix: &str,
+    info: &CommitInfo,
+) -> Result<W, Error> {
+    let mut export = FastExport::new(into)?;
+    for (nth, caidx) in indexes.iter().enumerate() {
+        let info = CommitInfo {
+            message: match info.message.as_str() {
+                "" => caidx.to_string(),
+                message => message.to_string(),
+            },
+            ..info.clone()
         };
+        export
+            .commit(
+                &format!("{}{}", ref_prefix, nth),
+                castr.open_index(caidx)?,
+                &info,
+            )
+            .with_context(|| format_err!("exporting index {}", caidx))?;
+    }
+    export.finish()
+}
+
+/// write out the whole stream an index describes, e.g. a disk image from a `.caibx`
+pub fn cat<W: Write>(mut into: W, castr: &ChunkSource, index: &str) -> Result<u64, Error> {
+    let mut stream = castr.open_index(index)?;
+    io::copy(&mut stream, &mut into).with_context(|| format_err!("writing out index {}", index))
+}
+
+/// archive `source`, a directory for a `.caidx`, or a file for a `.caibx`, into a store and an index
+pub fn make(
+    castr: &str,
+    index: &str,
+    source: &Path,
+    sizes: ChunkSize,
+    digest: ChunkDigest,
+    hardlinks: Hardlinks,
+) -> Result<StoreStats, Error> {
+    ensure!(
+        !is_url(castr),
+        "only local stores can be written to: {}",
+        castr
+    );
+    let kind = IndexKind::from_path(index)?;
+    let mut store = ChunkStore::new(castr)?.with_digest(digest);
+
+    // so a failure part way through doesn't leave a truncated index behind
+    let out = tempfile_fast::Sponge::new_for(index)
+        .with_context(|| format_err!("creating index {}", index))?;
+    crate::make(&mut store, out, kind, source, sizes, hardlinks)
+        .with_context(|| format_err!("archiving {:?}", source))?
+        .commit()
+        .with_context(|| format_err!("storing index {}", index))?;
+    Ok(*store.stats())
+}
+
+/// unpack the `catar` an index describes into `target`, returning anything which was skipped
+pub fn extract(
+    castr: &ChunkSource,
+    caidx: &str,
+    target: &Path,
+    owners: Owners,
+) -> Result<Vec<PathBuf>, Error> {
+    let stream = castr.open_index(caidx)?;
+    crate::extract(stream, target, owners).with_context(|| format_err!("extracting {}", caidx))
+}
 
-        match content {
-            casync_format::Content::File(mut data) => {
-                ensure!(last_entry.is_reg(), "TODO: data for non-regular file");
-
-                let executable = 0o100 == (last_entry.mode & 0o100);
-
-                writeln!(
-                    into,
-                    "M {} inline {}",
-                    if executable { "100755" } else { "100644" },
-                    casync_format::utf8_path(names)?
-                )?;
-                writeln!(into, "data {}", data.limit())?;
-                io::copy(&mut data, &mut io::stdout())?;
-            }
-            casync_format::Content::Directory => {
-                ensure!(last_entry.is_dir(), "directory end for non-directory");
-            }
-        }
+/// describe each packet of a `.catar`, or, with a store, of the stream an index describes
+pub fn dump<W: Write>(
+    into: W,
+    castr: Option<&ChunkSource>,
+    archive: &str,
+    format: DumpFormat,
+) -> Result<(), Error> {
+    let from: Box<dyn Read> = match castr {
+        Some(castr) => castr.open_index(archive)?,
+        None => Box::new(io::BufReader::new(
+            fs::File::open(archive).with_context(|| format_err!("opening {}", archive))?,
+        )),
+    };
+    crate::dump(from, into, format).with_context(|| format_err!("dumping {}", archive))
+}
+
+/// describe the `catar` an index describes as an mtree spec, like upstream's `casync mtree`
+pub fn mtree<W: Write>(into: W, castr: &ChunkSource, caidx: &str) -> Result<(), Error> {
+    crate::mtree(castr.open_index(caidx)?, into)
+        .with_context(|| format_err!("reading stream of index {}", caidx))
+}
+
+/// Delete the chunks in the store at `castr` which none of `indexes` reference,
+/// or, for a `dry_run`, just find them.
+///
+/// Every index is read before anything is deleted.
+pub fn gc(castr: &str, indexes: &[String], dry_run: bool) -> Result<GcStats, Error> {
+    ensure!(
+        !is_url(castr),
+        "only local stores can be collected: {}",
+        castr
+    );
+
+    let mut live = HashSet::new();
+    for index in indexes {
+        let (_, chunks) = load_index(index)?;
+        live.extend(chunks.iter().map(|chunk| chunk.id));
     }
-    Ok(())
+
+    crate::gc(castr, &live, dry_run).with_context(|| format_err!("collecting store {}", castr))
 }
 
-pub fn mtree<W: Write>(mut into: W, castr: &str, caidx: &str) -> Result<(), Error> {
-    let mut stream = Stream::new(from_paths(caidx, castr, move |path: &str| fs::read(path))?);
-
-    while let Some(path_content) = stream
-        .next()
-        .with_context(|| format_err!("reading stream of index {}", caidx))?
-    {
-        let (path, content) = path_content;
-        let last = path.end().clone();
-        let names: Vec<Box<[u8]>> = path.into_iter().map(|item| item.name).collect();
-        writeln!(into, "{}, {:?}", casync_format::utf8_path(names)?, last)?;
-
-        match content {
-            casync_format::Content::File(mut data) => {
-                let mut buf = Vec::new();
-                data.read_to_end(&mut buf)?;
-            }
-            casync_format::Content::Directory => {}
-        }
+/// Check every chunk `index` references in the local store at `castr`,
+/// and, with `archive`, that the stream of a `.caidx` is a valid `catar`.
+///
+/// The archive is only read if every chunk is fine.
+pub fn verify(castr: &str, index: &str, archive: bool) -> Result<Verified, Error> {
+    ensure!(
+        !is_url(castr),
+        "only local stores can be verified: {}",
+        castr
+    );
+
+    let (_, chunks) = load_index(index)?;
+    let root = PathBuf::from(castr);
+    let mut verified = crate::verify(|cacnk: &str| fs::read(root.join(cacnk)), &chunks);
+
+    if archive && verified.is_ok() {
+        ensure!(
+            IndexKind::Catar == IndexKind::from_path(index)?,
+            "only the stream of a .caidx is an archive: {}",
+            index
+        );
+        verified.items = Some(
+            crate::check_archive(ChunkSource::Local(root).open(chunks))
+                .with_context(|| format_err!("checking archive of index {}", index))?,
+        );
     }
-    Ok(())
+
+    Ok(verified)
 }
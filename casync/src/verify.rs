@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::Read;
+
+use anyhow::Error;
+use anyhow::format_err;
+use casync_format::Chunk;
+use casync_format::ChunkId;
+use casync_format::Content;
+use casync_format::Fetcher;
+use casync_format::Stream;
+use casync_format::chunks::load_chunk;
+
+/// What `verify` found in a store.
+#[derive(Debug, Default)]
+pub struct Verified {
+    /// how many of the index's chunks were checked, counting repeats
+    pub chunks: u64,
+    /// the length of the stream the good chunks make up
+    pub bytes: u64,
+    /// every chunk which is missing or corrupt, once each, and every place in the index
+    /// where a chunk is the wrong size
+    pub problems: Vec<(Chunk, casync_format::Error)>,
+    /// how many items the archive has, if it was checked, by `tools::verify`
+    pub items: Option<u64>,
+}
+
+impl Verified {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Check every chunk of an index, from `fetcher`: that it's there, decompresses,
+/// has the content its id says, and is as long as the index says.
+///
+/// `fetcher` is given chunk paths like `abcd/abcdefg012[..]30.cacnk`, as with `from_chunks`.
+/// Unlike reading the stream, this carries on past bad chunks, to find all of them.
+/// Chunks which the index repeats are only fetched once.
+pub fn verify<F: Fetcher>(mut fetcher: F, chunks: &[Chunk]) -> Verified {
+    let mut verified = Verified::default();
+    // the length of each chunk which has been read, or `None` if it was bad
+    let mut seen: HashMap<ChunkId, Option<u64>> = HashMap::new();
+
+    let mut start = 0;
+    for chunk in chunks {
+        verified.chunks += 1;
+        let expected = chunk.offset.saturating_sub(start);
+        start = chunk.offset;
+
+        let len = match seen.get(&chunk.id) {
+            Some(&len) => len,
+            None => {
+                let len = match load_chunk(&mut fetcher, chunk) {
+                    Ok(data) => Some(data.len() as u64),
+                    Err(e) => {
+                        verified.problems.push((*chunk, e));
+                        None
+                    }
+                };
+                seen.insert(chunk.id, len);
+                len
+            }
+        };
+
+        match len {
+            Some(len) if len == expected => verified.bytes += len,
+            Some(len) => verified.problems.push((
+                *chunk,
+                casync_format::Error::ChunkSize {
+                    id: chunk.id,
+                    expected,
+                    actual: len,
+                },
+            )),
+            None => (),
+        }
+    }
+
+    verified
+}
+
+/// Read the whole `catar` in `from`, including every file's content, to check its structure,
+/// returning how many items it has.
+pub fn check_archive<R: Read>(from: R) -> Result<u64, Error> {
+    let mut items = 0;
+    let mut stream = Stream::new(from);
+    while let Some((_path, content)) = stream.next()? {
+        if let Content::File(mut data) = content {
+            let expected = data.limit();
+            let read = io::copy(&mut data, &mut io::sink())?;
+            if read != expected {
+                return Err(format_err!(
+                    "file content truncated: {} of {} bytes",
+                    read,
+                    expected
+                ));
+            }
+        }
+        items += 1;
+    }
+    Ok(items)
+}
+
+impl fmt::Display for Verified {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "checked {} chunks ({} bytes): {} problems",
+            self.chunks,
+            self.bytes,
+            self.problems.len()
+        )?;
+        if let Some(items) = self.items {
+            write!(f, "; archive of {} items is valid", items)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs;
+use std::io::Read;
+
+use anyhow::Error;
+use casync::ChunkStore;
+use casync::StoreStats;
+use casync_format::Chunk;
+use casync_format::chunks::from_chunks;
+
+#[test]
+fn insert_and_read_back() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path().join("store.castr");
+    let mut store = ChunkStore::new(&root)?;
+
+    let hello = b"hello ".repeat(100);
+    let world = b"world".to_vec();
+
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    for data in &[&hello, &world, &hello] {
+        offset += data.len() as u64;
+        chunks.push(Chunk {
+            offset,
+            id: store.insert(data)?,
+            digest: store.digest(),
+        });
+    }
+
+    assert_eq!(chunks[0].id, chunks[2].id);
+    assert!(store.chunk_path(&chunks[1].id).is_file());
+
+    let stats = *store.stats();
+    assert_eq!(2, stats.new_chunks);
+    assert_eq!(605, stats.new_bytes);
+    assert!(stats.stored_bytes < stats.new_bytes);
+    assert_eq!(1, stats.existing_chunks);
+    assert_eq!(600, stats.existing_bytes);
+
+    let mut read = Vec::new();
+    from_chunks(chunks, move |path: &str| fs::read(root.join(path))).read_to_end(&mut read)?;
+    assert_eq!([&hello[..], &world, &hello].concat(), read);
+
+    // a second store on the same directory finds everything
+    let mut again = ChunkStore::new(dir.path().join("store.castr"))?;
+    again.insert(&world)?;
+    assert_eq!(
+        &StoreStats {
+            existing_chunks: 1,
+            existing_bytes: 5,
+            ..StoreStats::default()
+        },
+        again.stats()
+    );
+
+    Ok(())
+}
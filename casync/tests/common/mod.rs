@@ -0,0 +1,26 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Error;
+use casync::ChunkStore;
+use casync::Hardlinks;
+use casync_format::ChunkSize;
+use casync_format::IndexKind;
+
+/// archive `source`, a directory for a `Catar` or a file for a `Blob`, into `store`,
+/// with chunks of around `avg` bytes, and write the index next to it, e.g. `source.caibx`,
+/// returning the index's path
+pub fn publish(
+    store: &mut ChunkStore,
+    source: &Path,
+    kind: IndexKind,
+    avg: u64,
+) -> Result<String, Error> {
+    let index = format!("{}{}", source.to_str().unwrap(), kind.extension());
+    let sizes = ChunkSize::from_avg(avg)?;
+    fs::write(
+        &index,
+        casync::make(store, Vec::new(), kind, source, sizes, Hardlinks::Copy)?,
+    )?;
+    Ok(index)
+}
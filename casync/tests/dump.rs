@@ -0,0 +1,48 @@
+use anyhow::Error;
+use casync::DumpFormat;
+
+const TWO: &[u8] = include_bytes!("../../casync-format/tests/data/two.catar");
+
+#[test]
+fn text() -> Result<(), Error> {
+    let mut out = Vec::new();
+    casync::dump(TWO, &mut out, DumpFormat::Text)?;
+    let out = String::from_utf8(out)?;
+    let lines: Vec<&str> = out.lines().collect();
+
+    assert_eq!(14, lines.len());
+    assert!(lines[0].starts_with("         0 Entry (64) mode: 0o40755, uid: 1000"));
+    assert_eq!("        64   Name (18) text: \"b\"", lines[1]);
+    assert_eq!("       232     Data (21) len: 5", lines[5]);
+    assert_eq!("       359   Bye (88) items: 2", lines[9]);
+    assert_eq!("       553 Bye (88) items: 2", lines[13]);
+    Ok(())
+}
+
+#[test]
+fn json() -> Result<(), Error> {
+    let mut out = Vec::new();
+    casync::dump(TWO, &mut out, DumpFormat::Json)?;
+    let out = String::from_utf8(out)?;
+    let lines: Vec<&str> = out.lines().collect();
+
+    assert_eq!(
+        "{\"offset\":146,\"size\":22,\"type\":\"Name\",\"depth\":2,\"text\":\"three\"}",
+        lines[3]
+    );
+    assert!(lines[4].contains("\"mode\":33188,"), "{}", lines[4]);
+    assert!(
+        lines[4].contains("\"mtime\":1514074354015146000,"),
+        "{}",
+        lines[4]
+    );
+    Ok(())
+}
+
+#[test]
+fn invalid() {
+    // everything before the problem is still described
+    let mut out = Vec::new();
+    assert!(casync::dump(&TWO[..240], &mut out, DumpFormat::Text).is_err());
+    assert_eq!(5, String::from_utf8(out).unwrap().lines().count());
+}
@@ -0,0 +1,336 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::symlink;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+
+use anyhow::Error;
+use anyhow::ensure;
+use casync::ChunkStore;
+use casync::Hardlinks;
+use casync::Owners;
+use casync::extract;
+use casync::make;
+use casync_format::CatarWriter;
+use casync_format::ChunkSize;
+use casync_format::Content;
+use casync_format::Entry;
+use casync_format::Goodbye;
+use casync_format::GoodbyeItem;
+use casync_format::IndexKind;
+use casync_format::IndexReader;
+use casync_format::Stream;
+use casync_format::StreamMagic;
+use casync_format::hash_name;
+use casync_format::read_index;
+
+fn assert_same(source: &Path, extracted: &Path) -> Result<(), Error> {
+    let expected = fs::symlink_metadata(source)?;
+    let actual = fs::symlink_metadata(extracted)?;
+    assert_eq!(expected.mode(), actual.mode(), "{:?}", extracted);
+    assert_eq!(expected.uid(), actual.uid(), "{:?}", extracted);
+    assert_eq!(expected.gid(), actual.gid(), "{:?}", extracted);
+
+    if expected.is_symlink() {
+        assert_eq!(fs::read_link(source)?, fs::read_link(extracted)?);
+        return Ok(());
+    }
+
+    assert_eq!(expected.mtime(), actual.mtime(), "{:?}", extracted);
+    assert_eq!(
+        expected.mtime_nsec(),
+        actual.mtime_nsec(),
+        "{:?}",
+        extracted
+    );
+
+    if expected.is_dir() {
+        let mut names = Vec::new();
+        for dirent in fs::read_dir(source)? {
+            let name = dirent?.file_name();
+            if "socket" != name {
+                names.push(name);
+            }
+        }
+        assert_eq!(
+            names.len(),
+            fs::read_dir(extracted)?.count(),
+            "{:?}",
+            extracted
+        );
+        for name in names {
+            assert_same(&source.join(&name), &extracted.join(&name))?;
+        }
+    } else if expected.is_file() {
+        assert_eq!(fs::read(source)?, fs::read(extracted)?);
+    } else {
+        // fifos and devices, which mustn't be read
+        assert_eq!(expected.rdev(), actual.rdev(), "{:?}", extracted);
+    }
+
+    Ok(())
+}
+
+/// `mknod`, returning whether we're allowed to
+fn mknod(path: &Path, mode: libc::mode_t, dev: libc::dev_t) -> Result<bool, Error> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    if 0 == unsafe { libc::mknod(path.as_ptr(), mode, dev) } {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    ensure!(io::ErrorKind::PermissionDenied == e.kind(), e);
+    Ok(false)
+}
+
+#[test]
+fn round_trip() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let source = dir.path().join("source");
+    fs::create_dir_all(source.join("sub/deeper"))?;
+    fs::create_dir(source.join("read-only"))?;
+    fs::write(source.join("read-only/hello"), b"hello\n")?;
+    fs::write(
+        source.join("sub/numbers"),
+        (0..5000).map(|i| format!("{}\n", i)).collect::<String>(),
+    )?;
+    fs::write(source.join("sub/deeper/script"), b"#!/bin/sh\n")?;
+    fs::set_permissions(
+        source.join("sub/deeper/script"),
+        fs::Permissions::from_mode(0o750),
+    )?;
+    fs::set_permissions(source.join("read-only"), fs::Permissions::from_mode(0o555))?;
+    symlink("deeper/script", source.join("sub/link"))?;
+    UnixListener::bind(source.join("socket"))?;
+    assert!(mknod(&source.join("sub/pipe"), libc::S_IFIFO | 0o640, 0)?);
+    // only when running as root, with `CAP_MKNOD`
+    let devices = mknod(
+        &source.join("null"),
+        libc::S_IFCHR | 0o666,
+        libc::makedev(1, 3),
+    )?;
+
+    let mut store = ChunkStore::new(dir.path().join("store.castr"))?;
+    let index = make(
+        &mut store,
+        Vec::new(),
+        IndexKind::Catar,
+        &source,
+        ChunkSize::from_avg(1024)?,
+        Hardlinks::Copy,
+    )?;
+    let (_, chunks) = read_index(io::Cursor::new(&index))?;
+    let root = store.root().to_path_buf();
+    let stream = IndexReader::new(chunks, move |path: &str| fs::read(root.join(path)));
+
+    let target = dir.path().join("target");
+    let skipped = extract(stream, &target, Owners::Numeric)?;
+    assert_eq!(vec![target.join("socket")], skipped);
+    assert!(
+        fs::symlink_metadata(target.join("sub/pipe"))?
+            .file_type()
+            .is_fifo()
+    );
+    if devices {
+        assert!(
+            fs::symlink_metadata(target.join("null"))?
+                .file_type()
+                .is_char_device()
+        );
+    }
+
+    assert_same(&source, &target)
+}
+
+fn packet(into: &mut Vec<u8>, magic: StreamMagic, payload: &[u8]) {
+    into.extend_from_slice(&(16 + payload.len() as u64).to_le_bytes());
+    into.extend_from_slice(&magic.value().to_le_bytes());
+    into.extend_from_slice(payload);
+}
+
+fn entry(mode: u64) -> Vec<u8> {
+    [0, mode, 0, 0, 0, 0]
+        .iter()
+        .flat_map(|val: &u64| val.to_le_bytes())
+        .collect()
+}
+
+/// a directory containing a single file called `name`
+fn archive_with(name: &[u8]) -> Vec<u8> {
+    let mut archive = Vec::new();
+    packet(&mut archive, StreamMagic::Entry, &entry(0o40755));
+
+    let name_start = archive.len() as u64;
+    packet(&mut archive, StreamMagic::Name, &[name, b"\0"].concat());
+    packet(&mut archive, StreamMagic::Entry, &entry(0o100644));
+    packet(&mut archive, StreamMagic::Data, b"escaped\n");
+
+    let start = archive.len() as u64;
+    let table = Goodbye::from_items(
+        vec![GoodbyeItem {
+            hash: hash_name(name),
+            offset: start - name_start,
+            size: start - name_start,
+        }],
+        start,
+    );
+    packet(&mut archive, StreamMagic::Bye, &table.to_payload());
+    archive
+}
+
+#[test]
+fn traversal() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let target = dir.path().join("target");
+
+    extract(
+        io::Cursor::new(archive_with(b"fine")),
+        &target,
+        Owners::Leave,
+    )?;
+    assert_eq!(b"escaped\n", &fs::read(target.join("fine"))?[..]);
+
+    for name in &[&b".."[..], b".", b"../escaped", b"/tmp/escaped", b"a\0b"] {
+        assert!(
+            extract(io::Cursor::new(archive_with(name)), &target, Owners::Leave).is_err(),
+            "{:?}",
+            String::from_utf8_lossy(name)
+        );
+    }
+
+    assert!(!dir.path().join("escaped").exists());
+    Ok(())
+}
+
+/// an existing symlink isn't followed when replacing, or creating directories
+#[test]
+fn symlinks_in_target() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let target = dir.path().join("target");
+    let outside = dir.path().join("outside");
+    fs::create_dir_all(&target)?;
+    fs::write(&outside, b"untouched")?;
+    symlink(&outside, target.join("fine"))?;
+
+    extract(
+        io::Cursor::new(archive_with(b"fine")),
+        &target,
+        Owners::Leave,
+    )?;
+    assert_eq!(b"untouched", &fs::read(&outside)?[..]);
+    assert!(!fs::symlink_metadata(target.join("fine"))?.is_symlink());
+    Ok(())
+}
+
+#[test]
+fn hardlinks() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let source = dir.path().join("source");
+    fs::create_dir_all(source.join("sub"))?;
+    fs::write(source.join("a"), b"shared\n")?;
+    fs::hard_link(source.join("a"), source.join("c"))?;
+    fs::hard_link(source.join("a"), source.join("sub/b"))?;
+
+    let mut store = ChunkStore::new(dir.path().join("store.castr"))?;
+    let root = store.root().to_path_buf();
+    let fetch = move |path: &str| fs::read(root.join(path));
+    let archive = |store: &mut ChunkStore, hardlinks| -> Result<_, Error> {
+        let index = make(
+            store,
+            Vec::new(),
+            IndexKind::Catar,
+            &source,
+            ChunkSize::from_avg(1024)?,
+            hardlinks,
+        )?;
+        Ok(read_index(io::Cursor::new(&index))?.1)
+    };
+    let links = |chunks: Vec<_>| -> Result<_, Error> {
+        let mut links = Vec::new();
+        let mut stream = Stream::new(IndexReader::new(chunks, fetch.clone()));
+        while let Some((path, content)) = stream.next()? {
+            match content {
+                Content::File(mut data) => {
+                    io::copy(&mut data, &mut io::sink())?;
+                }
+                Content::Hardlink(target) => {
+                    let names: Vec<Box<[u8]>> = path.into_iter().map(|item| item.name).collect();
+                    links.push((
+                        casync_format::utf8_path(names)?,
+                        String::from_utf8(target.into_vec())?,
+                    ));
+                }
+                _ => (),
+            }
+        }
+        Ok(links)
+    };
+
+    // by default, as upstream: every name is a separate file
+    let chunks = archive(&mut store, Hardlinks::Copy)?;
+    assert!(links(chunks.clone())?.is_empty());
+    let target = dir.path().join("copied");
+    extract(
+        IndexReader::new(chunks, fetch.clone()),
+        &target,
+        Owners::Numeric,
+    )?;
+    assert_eq!(b"shared\n", &fs::read(target.join("sub/b"))?[..]);
+    assert_eq!(1, fs::metadata(target.join("a"))?.nlink());
+
+    let chunks = archive(&mut store, Hardlinks::Link)?;
+    assert_eq!(
+        vec![
+            ("./c".to_string(), "a".to_string()),
+            ("./sub/b".to_string(), "a".to_string())
+        ],
+        links(chunks.clone())?
+    );
+
+    let target = dir.path().join("target");
+    extract(IndexReader::new(chunks, fetch), &target, Owners::Numeric)?;
+    assert_same(&source, &target)?;
+    let inode = fs::metadata(target.join("a"))?.ino();
+    assert_eq!(inode, fs::metadata(target.join("c"))?.ino());
+    assert_eq!(inode, fs::metadata(target.join("sub/b"))?.ino());
+    assert_eq!(3, fs::metadata(target.join("a"))?.nlink());
+    Ok(())
+}
+
+/// a hardlink can't reach outside of the target through a symlink in the archive
+#[test]
+fn hardlink_through_symlink() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let target = dir.path().join("target");
+    let outside = dir.path().join("outside");
+    fs::create_dir_all(&outside)?;
+    fs::write(outside.join("secret"), b"private")?;
+
+    let entry = |mode| Entry {
+        mode,
+        ..Entry::default()
+    };
+    let mut writer = CatarWriter::new(Vec::new(), &entry(0o40755))?;
+    writer.add_symlink(b"evil", &entry(0o120777), outside.as_os_str().as_bytes())?;
+    writer.add_hardlink(b"stolen", &entry(0o100644), b"evil/secret")?;
+    let archive = writer.finish()?;
+
+    assert!(extract(io::Cursor::new(archive), &target, Owners::Leave).is_err());
+    assert!(!target.join("stolen").exists());
+    assert_eq!(1, fs::metadata(outside.join("secret"))?.nlink());
+    Ok(())
+}
+
+/// only root can give files away, so nobody else tries to by default
+#[test]
+fn default_owners() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let root = 0 == fs::metadata(dir.path())?.uid();
+    let expected = if root { Owners::Numeric } else { Owners::Leave };
+    assert_eq!(expected, Owners::for_current_user());
+    Ok(())
+}
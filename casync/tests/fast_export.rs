@@ -0,0 +1,121 @@
+use anyhow::Error;
+use casync::CommitInfo;
+use casync::FastExport;
+use casync_format::CatarWriter;
+use casync_format::Entry;
+use casync_format::Timestamp;
+
+fn entry(mode: u64) -> Entry {
+    Entry {
+        mode,
+        mtime: Timestamp::from_nanos(1_500_000_000_999_999_999),
+        ..Entry::default()
+    }
+}
+
+fn archive(extra: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut writer = CatarWriter::new(Vec::new(), &entry(0o40755))?;
+    writer.begin_dir(b"bin", &entry(0o40755))?;
+    writer.add_file(b"tool", &entry(0o100755), &b"#!/bin/sh\n"[..])?;
+    writer.add_hardlink(b"tool-alias", &entry(0o100755), b"bin/tool")?;
+    writer.end_dir()?;
+    writer.add_symlink(b"link", &entry(0o120777), b"bin/tool")?;
+    writer.add_file(b"new\nline", &entry(0o100644), extra)?;
+    writer.add_special(b"pipe", &entry(0o10644))?;
+    Ok(writer.finish()?)
+}
+
+#[test]
+fn history() -> Result<(), Error> {
+    let info = CommitInfo {
+        author: "Jo Bloggs <jo@example.com>".to_string(),
+        time: None,
+        message: "first".to_string(),
+    };
+
+    let mut export = FastExport::new(Vec::new())?;
+    export.commit("refs/heads/main", &archive(b"one")?[..], &info)?;
+    let info = CommitInfo {
+        time: Some(1_600_000_000),
+        message: "second".to_string(),
+        ..info
+    };
+    export.commit("refs/heads/main", &archive(b"two")?[..], &info)?;
+    let out = String::from_utf8(export.finish()?)?;
+
+    assert_eq!(
+        "feature done
+blob
+mark :1
+data 10
+#!/bin/sh
+
+blob
+mark :2
+data 8
+bin/tool
+blob
+mark :3
+data 3
+one
+commit refs/heads/main
+mark :4
+author Jo Bloggs <jo@example.com> 1500000000 +0000
+committer Jo Bloggs <jo@example.com> 1500000000 +0000
+data 5
+first
+deleteall
+M 100755 :1 bin/tool
+M 100755 :1 bin/tool-alias
+M 120000 :2 link
+M 100644 :3 \"new\\nline\"
+
+blob
+mark :5
+data 3
+two
+commit refs/heads/main
+mark :6
+author Jo Bloggs <jo@example.com> 1600000000 +0000
+committer Jo Bloggs <jo@example.com> 1600000000 +0000
+data 6
+second
+from :4
+deleteall
+M 100755 :1 bin/tool
+M 100755 :1 bin/tool-alias
+M 120000 :2 link
+M 100644 :5 \"new\\nline\"
+
+done
+",
+        out
+    );
+    Ok(())
+}
+
+/// a file claiming to be enormous is an error, not an attempt to allocate it all
+#[test]
+fn huge_file() -> Result<(), Error> {
+    let mut archive = archive(b"short")?;
+    let data = casync_format::PacketReader::new(&archive[..])
+        .map(|packet| packet.expect("valid"))
+        .filter(|packet| casync_format::StreamMagic::Data == packet.magic)
+        .last()
+        .expect("a file");
+    let offset = data.offset as usize;
+    archive[offset..offset + 8].copy_from_slice(&(1u64 << 50).to_le_bytes());
+
+    let info = CommitInfo {
+        author: "Jo Bloggs <jo@example.com>".to_string(),
+        time: None,
+        message: "huge".to_string(),
+    };
+    let mut export = FastExport::new(Vec::new())?;
+    assert!(
+        export
+            .commit("refs/heads/main", &archive[..], &info)
+            .is_err()
+    );
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::cell::Cell;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Error;
+use casync::AsyncFetcher;
+use casync::ChunkStore;
+use casync::copy_chunks;
+use casync_format::Chunk;
+
+/// a local store, which is slow, and slowest for the earliest chunks
+struct SlowStore {
+    root: PathBuf,
+    delays: Vec<(String, u64)>,
+    in_flight: Cell<usize>,
+    most_in_flight: Cell<usize>,
+}
+
+impl AsyncFetcher for SlowStore {
+    async fn fetch(&self, chunk: &Chunk) -> Result<Vec<u8>, Error> {
+        let cacnk = chunk.format_id();
+        self.in_flight.set(self.in_flight.get() + 1);
+        self.most_in_flight
+            .set(self.most_in_flight.get().max(self.in_flight.get()));
+
+        let (_, delay) = self
+            .delays
+            .iter()
+            .find(|(path, _)| *path == cacnk)
+            .expect("known chunk");
+        tokio::time::sleep(Duration::from_millis(*delay)).await;
+
+        self.in_flight.set(self.in_flight.get() - 1);
+        Ok(fs::read(self.root.join(&cacnk))?)
+    }
+}
+
+fn populate(store: &mut ChunkStore, count: u64) -> Result<(Vec<Chunk>, Vec<u8>), Error> {
+    let mut chunks = Vec::new();
+    let mut expected = Vec::new();
+    for i in 0..count {
+        let data = format!("chunk number {}\n", i).repeat(100).into_bytes();
+        expected.extend_from_slice(&data);
+        chunks.push(Chunk {
+            offset: expected.len() as u64,
+            id: store.insert(&data)?,
+            digest: store.digest(),
+        });
+    }
+    Ok((chunks, expected))
+}
+
+#[tokio::test]
+async fn in_order() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let mut store = ChunkStore::new(dir.path())?;
+    let (chunks, expected) = populate(&mut store, 20)?;
+
+    let fetcher = SlowStore {
+        root: dir.path().to_path_buf(),
+        delays: chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| (chunk.format_id(), 40 - 2 * i as u64))
+            .collect(),
+        in_flight: Cell::new(0),
+        most_in_flight: Cell::new(0),
+    };
+
+    let mut out = Vec::new();
+    let written = copy_chunks(&fetcher, chunks, 4, &mut out).await?;
+    assert_eq!(expected.len() as u64, written);
+    assert_eq!(expected, out);
+    assert_eq!(4, fetcher.most_in_flight.get());
+    Ok(())
+}
+
+#[tokio::test]
+async fn corrupt() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let mut store = ChunkStore::new(dir.path())?;
+    let (chunks, _) = populate(&mut store, 3)?;
+
+    // the second chunk's file has the third's content
+    fs::copy(
+        store.chunk_path(&chunks[2].id),
+        store.chunk_path(&chunks[1].id),
+    )?;
+    let fetcher = SlowStore {
+        root: dir.path().to_path_buf(),
+        delays: chunks.iter().map(|chunk| (chunk.format_id(), 0)).collect(),
+        in_flight: Cell::new(0),
+        most_in_flight: Cell::new(0),
+    };
+
+    let mut out = Vec::new();
+    let err = copy_chunks(&fetcher, chunks, 2, &mut out)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("checksum mismatch"));
+    Ok(())
+}
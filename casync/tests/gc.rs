@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Error;
+use casync::ChunkSource;
+use casync::ChunkStore;
+use casync_format::IndexKind;
+
+mod common;
+
+/// archive a file of `count` numbers into `store`, as `name`
+fn publish(dir: &Path, store: &mut ChunkStore, name: &str, count: u32) -> Result<String, Error> {
+    let image = dir.join(name);
+    let content: String = (0..count).map(|i| format!("{}\n", i)).collect();
+    fs::write(&image, content)?;
+    common::publish(store, &image, IndexKind::Blob, 1024)
+}
+
+#[test]
+fn collect() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path().join("store.castr");
+    let mut store = ChunkStore::new(&root)?;
+    let kept = publish(dir.path(), &mut store, "kept", 5_000)?;
+    let dropped = publish(dir.path(), &mut store, "dropped", 20_000)?;
+    let castr = root.to_str().unwrap();
+
+    // things which aren't chunks are left alone
+    fs::create_dir_all(root.join("quarantine"))?;
+    fs::write(root.join("quarantine/oops.cacnk"), b"bad")?;
+    fs::write(root.join("notes.txt"), b"hello")?;
+
+    let chunks = |index: &str| -> Result<usize, Error> { Ok(casync::load_index(index)?.1.len()) };
+    let before = fs::read_dir(&root)?.count();
+    let live = vec![kept.clone()];
+
+    let stats = casync::tools::gc(castr, &live, true)?;
+    assert!(stats.dry_run);
+    assert_eq!(chunks(&kept)? as u64, stats.live_chunks);
+    assert!(!stats.dead.is_empty());
+    assert!(stats.reclaimed_bytes > 0);
+    assert!(stats.dead.iter().all(|path| path.is_file()));
+    assert_eq!(before, fs::read_dir(&root)?.count());
+
+    let deleted = casync::tools::gc(castr, &live, false)?;
+    assert_eq!(stats.dead, deleted.dead);
+    assert_eq!(stats.reclaimed_bytes, deleted.reclaimed_bytes);
+    assert!(deleted.dead.iter().all(|path| !path.exists()));
+    assert!(root.join("quarantine/oops.cacnk").exists());
+    assert!(root.join("notes.txt").exists());
+
+    let source = ChunkSource::new(castr, dir.path())?;
+    let mut out = Vec::new();
+    casync::tools::cat(&mut out, &source, &kept)?;
+    assert_eq!(fs::read(dir.path().join("kept"))?, out);
+    assert!(casync::tools::cat(Vec::new(), &source, &dropped).is_err());
+
+    // nothing left to do
+    let again = casync::tools::gc(castr, &live, false)?;
+    assert!(again.dead.is_empty());
+    assert_eq!(stats.live_chunks, again.live_chunks);
+    Ok(())
+}
+
+#[test]
+fn unreadable_index() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path().join("store.castr");
+    let mut store = ChunkStore::new(&root)?;
+    let kept = publish(dir.path(), &mut store, "kept", 5_000)?;
+    let missing = dir.path().join("missing.caidx");
+
+    // nothing is deleted if any of the indexes can't be read
+    let castr = root.to_str().unwrap();
+    assert!(casync::tools::gc(castr, &[missing.to_str().unwrap().to_string()], false).is_err());
+    let mut out = Vec::new();
+    casync::tools::cat(&mut out, &ChunkSource::new(castr, dir.path())?, &kept)?;
+    Ok(())
+}
@@ -0,0 +1,287 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::symlink;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+
+use anyhow::Error;
+use casync::ChunkStore;
+use casync::Hardlinks;
+use casync::make;
+use casync_format::CatarReader;
+use casync_format::ChunkDigest;
+use casync_format::ChunkSize;
+use casync_format::Content;
+use casync_format::IndexKind;
+use casync_format::IndexReader;
+use casync_format::Stream;
+use casync_format::read_index;
+
+fn populate(root: &Path) -> Result<(), Error> {
+    fs::create_dir_all(root.join("sub/deeper"))?;
+    fs::create_dir(root.join("empty"))?;
+    fs::write(root.join("hello"), b"hello\n")?;
+    fs::write(
+        root.join("sub/numbers"),
+        (0..5000).map(|i| format!("{}\n", i)).collect::<String>(),
+    )?;
+    fs::write(root.join("sub/deeper/empty-file"), b"")?;
+    fs::set_permissions(root.join("hello"), fs::Permissions::from_mode(0o755))?;
+    symlink("../hello", root.join("sub/link"))?;
+    UnixListener::bind(root.join("socket"))?;
+    Ok(())
+}
+
+#[test]
+fn round_trip() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let source = dir.path().join("source");
+    populate(&source)?;
+
+    let mut store = ChunkStore::new(dir.path().join("store.castr"))?;
+    let sizes = ChunkSize::from_avg(1024)?;
+    let index = make(
+        &mut store,
+        Vec::new(),
+        IndexKind::Catar,
+        &source,
+        sizes,
+        Hardlinks::Copy,
+    )?;
+    assert_eq!(0, store.stats().existing_chunks);
+
+    let (read_sizes, chunks) = read_index(io::Cursor::new(&index))?;
+    assert_eq!(sizes, read_sizes);
+    assert!(chunks.len() > 1, "small chunks, so there are a few");
+
+    let root = store.root().to_path_buf();
+    let fetch = move |path: &str| fs::read(root.join(path));
+
+    // all the goodbye tables are checked as the stream is read
+    let mut paths = Vec::new();
+    let mut stream = Stream::new(IndexReader::new(chunks.clone(), fetch.clone()));
+    while let Some((path, content)) = stream.next()? {
+        let names: Vec<Box<[u8]>> = path.clone().into_iter().map(|item| item.name).collect();
+        let name = casync_format::utf8_path(names)?;
+        let entry = path.end().entry.clone().unwrap();
+
+        let on_disk = fs::symlink_metadata(source.join(&name))?;
+        assert_eq!(u64::from(on_disk.mode()), entry.mode, "{}", name);
+        assert_eq!(u64::from(on_disk.uid()), entry.uid, "{}", name);
+
+        match content {
+            Content::File(mut data) => {
+                let mut buf = Vec::new();
+                data.read_to_end(&mut buf)?;
+                assert_eq!(fs::read(source.join(&name))?, buf, "{}", name);
+            }
+            Content::Symlink(target) => assert_eq!(b"../hello", &target[..]),
+            Content::Directory => assert!(entry.is_dir()),
+            Content::Socket => assert_eq!("./socket", name),
+            _ => panic!("unexpected content: {}", name),
+        }
+        paths.push(name);
+    }
+
+    assert_eq!(
+        vec![
+            "./empty",
+            "./hello",
+            "./socket",
+            "./sub/deeper/empty-file",
+            "./sub/deeper",
+            "./sub/link",
+            "./sub/numbers",
+            "./sub",
+            ".",
+        ],
+        paths
+    );
+
+    let mut reader = CatarReader::new(IndexReader::new(chunks, fetch))?;
+    match reader.lookup("sub/numbers")? {
+        Some((_, Content::File(mut data))) => {
+            let mut buf = String::new();
+            data.read_to_string(&mut buf)?;
+            assert!(buf.starts_with("0\n1\n2\n"));
+        }
+        _ => panic!("sub/numbers missing"),
+    }
+
+    // nothing has changed, so nothing new is stored, and the index is the same
+    let mut store = ChunkStore::new(store.root())?;
+    assert_eq!(
+        index,
+        make(
+            &mut store,
+            Vec::new(),
+            IndexKind::Catar,
+            &source,
+            sizes,
+            Hardlinks::Copy
+        )?
+    );
+    assert_eq!(0, store.stats().new_chunks);
+    Ok(())
+}
+
+#[test]
+fn blob() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let image = dir.path().join("image");
+    let content: Vec<u8> = (0..100_000u32)
+        .flat_map(|i| (i * 7).to_le_bytes())
+        .collect();
+    fs::write(&image, &content)?;
+
+    // the other digest
+    let mut store =
+        ChunkStore::new(dir.path().join("store.castr"))?.with_digest(ChunkDigest::Sha256);
+    let index = make(
+        &mut store,
+        Vec::new(),
+        IndexKind::Blob,
+        &image,
+        ChunkSize::default(),
+        Hardlinks::Copy,
+    )?;
+    let (_, chunks) = read_index(io::Cursor::new(&index))?;
+    assert!(chunks.iter().all(|c| ChunkDigest::Sha256 == c.digest));
+
+    let root = store.root().to_path_buf();
+    let mut read = Vec::new();
+    IndexReader::new(chunks, move |path: &str| fs::read(root.join(path))).read_to_end(&mut read)?;
+    assert_eq!(content, read);
+
+    assert!(
+        make(
+            &mut store,
+            Vec::new(),
+            IndexKind::Catar,
+            &image,
+            ChunkSize::default(),
+            Hardlinks::Copy
+        )
+        .is_err()
+    );
+    Ok(())
+}
+
+/// an index is only replaced once it's complete
+#[test]
+fn index_replaced_whole() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let castr = dir.path().join("store.castr");
+    let castr = castr.to_str().unwrap();
+    let index = dir.path().join("tree.caidx");
+    let index = index.to_str().unwrap();
+    let source = dir.path().join("tree");
+    fs::create_dir_all(&source)?;
+    fs::write(source.join("file"), b"hello")?;
+
+    let make = |source: &Path| {
+        casync::tools::make(
+            castr,
+            index,
+            source,
+            ChunkSize::default(),
+            ChunkDigest::Sha512_256,
+            Hardlinks::Copy,
+        )
+    };
+    make(&source)?;
+    let good = fs::read(index)?;
+    assert!(!good.is_empty());
+
+    assert!(make(&dir.path().join("missing")).is_err());
+
+    // not a directory called `http:`
+    assert!(
+        casync::tools::make(
+            "http://localhost:1/store.castr",
+            index,
+            &source,
+            ChunkSize::default(),
+            ChunkDigest::Sha512_256,
+            Hardlinks::Copy,
+        )
+        .is_err()
+    );
+    assert!(!Path::new("http:").exists());
+    assert_eq!(good, fs::read(index)?);
+    assert_eq!(
+        vec!["store.castr", "tree", "tree.caidx"],
+        sorted_names(dir.path())?
+    );
+    Ok(())
+}
+
+fn sorted_names(dir: &Path) -> Result<Vec<String>, Error> {
+    let mut names = fs::read_dir(dir)?
+        .map(|dirent| Ok(dirent?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, Error>>()?;
+    names.sort();
+    Ok(names)
+}
+
+/// as glibc: the old 8 bit major and minor, with the rest of each above them
+#[test]
+fn device_numbers() {
+    for (major, minor) in [
+        (1, 3),
+        (8, 0),
+        (4095, 0xfffff),
+        (5000, 0x12345),
+        (u32::MAX.into(), 0),
+    ] {
+        let dev = casync::makedev(major, minor);
+        assert_eq!((major, minor), (casync::major(dev), casync::minor(dev)));
+    }
+    assert_eq!(0x103, casync::makedev(1, 3));
+    assert_eq!(libc::makedev(5000, 0x12345), casync::makedev(5000, 0x12345));
+}
+
+/// linux only has 12 bits of major, but the 20 bits of minor must survive
+#[test]
+fn device_nodes() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let source = dir.path().join("source");
+    fs::create_dir(&source)?;
+    let node = CString::new(source.join("node").as_os_str().as_bytes())?;
+    let dev = libc::makedev(4095, 0xfffff);
+    if 0 != unsafe { libc::mknod(node.as_ptr(), libc::S_IFCHR | 0o600, dev) } {
+        // e.g. in a container without CAP_MKNOD
+        let e = io::Error::last_os_error();
+        assert_eq!(io::ErrorKind::PermissionDenied, e.kind(), "{}", e);
+        return Ok(());
+    }
+
+    let mut store = ChunkStore::new(dir.path().join("store.castr"))?;
+    let index = make(
+        &mut store,
+        Vec::new(),
+        IndexKind::Catar,
+        &source,
+        ChunkSize::default(),
+        Hardlinks::Copy,
+    )?;
+    let (_, chunks) = read_index(io::Cursor::new(&index))?;
+    let root = store.root().to_path_buf();
+    let mut stream = Stream::new(IndexReader::new(chunks, move |path: &str| {
+        fs::read(root.join(path))
+    }));
+
+    let mut devices = Vec::new();
+    while let Some((_path, content)) = stream.next()? {
+        if let Content::Device { major, minor } = content {
+            devices.push((major, minor));
+        }
+    }
+    assert_eq!(vec![(4095, 0xfffff)], devices);
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use anyhow::Error;
+use casync_format::CatarWriter;
+use casync_format::Entry;
+use casync_format::FeatureFlags;
+use casync_format::Timestamp;
+
+fn mtree(archive: &[u8]) -> Result<Vec<String>, Error> {
+    let mut out = Vec::new();
+    casync::mtree(archive, &mut out)?;
+    Ok(String::from_utf8(out)?
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+#[test]
+fn special() -> Result<(), Error> {
+    let lines = mtree(include_bytes!(
+        "../../casync-format/tests/data/special.catar"
+    ))?;
+    let common = "uid=1000 gid=1000 time=1514074354.015146000";
+    assert_eq!(
+        vec![
+            format!(". type=dir mode=755 {}", common),
+            format!("./dir type=dir mode=755 {}", common),
+            format!("./dir/pipe type=fifo mode=644 {}", common),
+            format!("./fifo type=fifo mode=600 {}", common),
+            format!(
+                "./file type=file mode=644 uid=1000 gid=1000 size=6 time=1514074354.015146000 \
+                 sha256digest=5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+            ),
+            "./link type=link uid=1000 gid=1000 link=file time=1514074354.015146000".to_string(),
+            "./null type=char mode=666 uid=1000 gid=1000 device=linux,1,3 time=1514074354.015146000"
+                .to_string(),
+            "./sda type=block mode=660 uid=1000 gid=1000 device=linux,8,0 time=1514074354.015146000"
+                .to_string(),
+            format!("./sock type=socket mode=755 {}", common),
+        ],
+        lines
+    );
+    Ok(())
+}
+
+#[test]
+fn escaping() -> Result<(), Error> {
+    let entry = |mode| Entry {
+        mode,
+        feature_flags: FeatureFlags::WITH_USER_NAMES | FeatureFlags::WITH_SEC_TIME,
+        mtime: Timestamp::from_nanos(1_500_000_000_000_000_000),
+        user_name: Some(b"Jo Bloggs"[..].into()),
+        ..Entry::default()
+    };
+    let mut writer = CatarWriter::new(Vec::new(), &entry(0o40755))?;
+    writer.add_file(b"a file#1\\\xe2\x98\x83", &entry(0o100600), &b""[..])?;
+    writer.add_hardlink(b"again", &entry(0o100600), b"a file#1\\\xe2\x98\x83")?;
+    let lines = mtree(&writer.finish()?)?;
+
+    let empty = "sha256digest=e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    assert_eq!(
+        vec![
+            ". type=dir mode=755 uname=Jo\\040Bloggs time=1500000000.000000000".to_string(),
+            format!(
+                "./a\\040file\\0431\\134\\342\\230\\203 type=file mode=600 uname=Jo\\040Bloggs \
+                 size=0 time=1500000000.000000000 {}",
+                empty
+            ),
+            format!(
+                "./again type=file mode=600 uname=Jo\\040Bloggs size=0 time=1500000000.000000000 {}",
+                empty
+            ),
+        ],
+        lines
+    );
+    Ok(())
+}
+
+/// as `mtree -f` needs: every directory before what's in it
+#[test]
+fn parents_first() -> Result<(), Error> {
+    let mut writer = CatarWriter::new(
+        Vec::new(),
+        &Entry {
+            mode: 0o40755,
+            ..Entry::default()
+        },
+    )?;
+    let dir = Entry {
+        mode: 0o40755,
+        ..Entry::default()
+    };
+    let file = Entry {
+        mode: 0o100644,
+        ..Entry::default()
+    };
+    writer.begin_dir(b"a", &dir)?;
+    writer.begin_dir(b"b", &dir)?;
+    writer.add_file(b"c", &file, &b""[..])?;
+    writer.end_dir()?;
+    writer.add_file(b"d", &file, &b""[..])?;
+    writer.begin_dir(b"empty", &dir)?;
+    writer.end_dir()?;
+    writer.end_dir()?;
+    writer.add_file(b"e", &file, &b""[..])?;
+    let lines = mtree(&writer.finish()?)?;
+
+    let paths: Vec<&str> = lines
+        .iter()
+        .map(|line| line.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(
+        vec![".", "./a", "./a/b", "./a/b/c", "./a/d", "./a/empty", "./e"],
+        paths
+    );
+    Ok(())
+}
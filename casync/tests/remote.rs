@@ -0,0 +1,174 @@
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Error;
+use casync::ChunkSource;
+use casync::ChunkStore;
+use casync_format::IndexKind;
+use casync_format::format_chunk_id;
+
+/// serve files from `root` over http, one request per connection, returning the base url
+fn serve(root: PathBuf) -> Result<String, Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    thread::spawn(move || {
+        for conn in listener.incoming() {
+            // clients which have given up are fine
+            let _ = conn.and_then(|conn| respond(conn, &root));
+        }
+    });
+    Ok(url)
+}
+
+fn respond(mut conn: TcpStream, root: &Path) -> io::Result<()> {
+    let mut reader = BufReader::new(conn.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    loop {
+        let mut header = String::new();
+        if 0 == reader.read_line(&mut header)? || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let path = request.split(' ').nth(1).unwrap_or("/");
+    let (status, body) = match fs::read(root.join(path.trim_start_matches('/'))) {
+        Ok(body) => ("200 OK", body),
+        Err(_) => ("404 Not Found", Vec::new()),
+    };
+    write!(
+        conn,
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    )?;
+    conn.write_all(&body)
+}
+
+mod common;
+
+/// archive an image into `served`, as `image.caibx` and `default.castr`, returning its content
+fn publish(served: &Path) -> Result<Vec<u8>, Error> {
+    let mut store = ChunkStore::new(served.join("default.castr"))?;
+    let image = served.join("image");
+    let content: Vec<u8> = (0..20_000u32)
+        .flat_map(|i| (i * 7919).to_le_bytes())
+        .collect();
+    fs::write(&image, &content)?;
+    common::publish(&mut store, &image, IndexKind::Blob, 4096)?;
+    Ok(content)
+}
+
+#[test]
+fn cat_over_http() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let served = dir.path().join("served");
+    let cache = dir.path().join("cache");
+    let content = publish(&served)?;
+
+    let url = serve(served.clone())?;
+    let source = ChunkSource::new(&format!("{}default.castr", url), &cache)?.with_parallelism(3);
+
+    let mut out = Vec::new();
+    casync::tools::cat(&mut out, &source, &format!("{}image.caibx", url))?;
+    assert_eq!(content, out);
+
+    // everything is now cached, so the server's chunks aren't needed
+    fs::remove_dir_all(served.join("default.castr"))?;
+    let mut out = Vec::new();
+    casync::tools::cat(&mut out, &source, &format!("{}image.caibx", url))?;
+    assert_eq!(content, out);
+
+    assert!(casync::tools::cat(Vec::new(), &source, &format!("{}missing.caibx", url)).is_err());
+
+    // .. but a fresh cache can't get them
+    let source = ChunkSource::new(&format!("{}default.castr", url), dir.path().join("fresh"))?;
+    assert!(casync::tools::cat(Vec::new(), &source, &format!("{}image.caibx", url)).is_err());
+    Ok(())
+}
+
+#[test]
+fn poisoned() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let served = dir.path().join("served");
+    let cache = dir.path().join("cache");
+    let content = publish(&served)?;
+    let (_, chunks) = casync::load_index(served.join("image.caibx").to_str().unwrap())?;
+    let first = format_chunk_id(&chunks[0].id);
+    let second = format_chunk_id(&chunks[1].id);
+
+    // the server has a truncated copy of the first chunk, which isn't cached
+    let good = fs::read(served.join("default.castr").join(&first))?;
+    fs::write(
+        served.join("default.castr").join(&first),
+        &good[..good.len() / 2],
+    )?;
+
+    let url = serve(served.clone())?;
+    let source = ChunkSource::new(&format!("{}default.castr", url), &cache)?;
+    let index = format!("{}image.caibx", url);
+    assert!(casync::tools::cat(Vec::new(), &source, &index).is_err());
+    assert!(!cache.join(&first).exists());
+
+    // the server recovers, but the cache has been damaged
+    fs::write(served.join("default.castr").join(&first), &good)?;
+    fs::create_dir_all(cache.join(&second).parent().unwrap())?;
+    fs::write(cache.join(&second), b"not even zstd")?;
+
+    let mut out = Vec::new();
+    casync::tools::cat(&mut out, &source, &index)?;
+    assert_eq!(content, out);
+
+    let name = second.rsplit('/').next().unwrap();
+    assert_eq!(
+        b"not even zstd",
+        &fs::read(cache.join("quarantine").join(name))?[..]
+    );
+    assert_eq!(
+        fs::read(served.join("default.castr").join(&second))?,
+        fs::read(cache.join(&second))?
+    );
+    Ok(())
+}
+
+/// nothing more is downloaded once a chunk can't be
+#[test]
+fn stops_at_errors() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let served = dir.path().join("served");
+    let cache = dir.path().join("cache");
+    publish(&served)?;
+    let (_, chunks) = casync::load_index(served.join("image.caibx").to_str().unwrap())?;
+    assert!(chunks.len() > 3, "{}", chunks.len());
+    fs::remove_file(
+        served
+            .join("default.castr")
+            .join(format_chunk_id(&chunks[0].id)),
+    )?;
+
+    let url = serve(served.clone())?;
+    let source = ChunkSource::new(&format!("{}default.castr", url), &cache)?.with_parallelism(1);
+    let mut stream = source.open(chunks);
+    assert!(io::copy(&mut stream, &mut io::sink()).is_err());
+
+    // the reader is still around, so a download which carried on could keep going
+    thread::sleep(Duration::from_millis(200));
+    let mut cached = Vec::new();
+    for prefix in fs::read_dir(&cache)? {
+        for chunk in fs::read_dir(prefix?.path())? {
+            cached.push(chunk?.path());
+        }
+    }
+    assert!(cached.is_empty(), "{:?}", cached);
+    drop(stream);
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::io::Read;
+
+use anyhow::Error;
+use casync::ChunkSource;
+use casync::ChunkStore;
+use casync_format::IndexKind;
+
+mod common;
+
+#[test]
+fn problems() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path().join("store.castr");
+    let mut store = ChunkStore::new(&root)?;
+    let image = dir.path().join("nums");
+    let content: String = (0..20_000).map(|i| format!("{}\n", i)).collect();
+    fs::write(&image, &content)?;
+    let index = common::publish(&mut store, &image, IndexKind::Blob, 1024)?;
+    let castr = root.to_str().unwrap();
+
+    let (_, chunks) = casync::load_index(&index)?;
+    assert!(chunks.len() > 3, "{}", chunks.len());
+
+    let verified = casync::tools::verify(castr, &index, false)?;
+    assert!(verified.is_ok(), "{:?}", verified.problems);
+    assert_eq!(chunks.len() as u64, verified.chunks);
+    assert_eq!(content.len() as u64, verified.bytes);
+    assert_eq!(None, verified.items);
+
+    fs::remove_file(root.join(chunks[0].format_id()))?;
+    fs::write(root.join(chunks[1].format_id()), b"not zstd")?;
+    fs::write(
+        root.join(chunks[2].format_id()),
+        zstd::encode_all(&b"something else"[..], 3)?,
+    )?;
+
+    let verified = casync::tools::verify(castr, &index, false)?;
+    let found: Vec<_> = verified
+        .problems
+        .iter()
+        .map(|(chunk, problem)| (chunk.id, problem.to_string()))
+        .collect();
+    assert_eq!(3, found.len(), "{:?}", found);
+    assert_eq!(chunks[0].id, found[0].0);
+    assert!(found[0].1.starts_with("chunk missing: "), "{}", found[0].1);
+    assert_eq!(chunks[1].id, found[1].0);
+    assert!(found[1].1.starts_with("corrupt chunk "), "{}", found[1].1);
+    assert_eq!(chunks[2].id, found[2].0);
+    assert!(
+        found[2].1.starts_with("checksum mismatch: "),
+        "{}",
+        found[2].1
+    );
+
+    // the archive isn't read if the chunks are bad, and a .caibx isn't an archive anyway
+    assert!(casync::tools::verify(castr, &index, true)?.items.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn sizes() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path().join("store.castr");
+    let mut store = ChunkStore::new(&root)?;
+    let image = dir.path().join("nums");
+    let content: String = (0..5_000).map(|i| format!("{}\n", i)).collect();
+    fs::write(&image, &content)?;
+    let index = common::publish(&mut store, &image, IndexKind::Blob, 1024)?;
+
+    let (_, mut chunks) = casync::load_index(&index)?;
+    chunks[0].offset -= 1;
+
+    let verified = casync::verify(|cacnk: &str| fs::read(root.join(cacnk)), &chunks);
+    assert_eq!(2, verified.problems.len(), "{:?}", verified.problems);
+    assert_eq!(
+        format!(
+            "chunk {} is {} bytes, but the index says {}",
+            chunks[0].format_id(),
+            chunks[0].offset + 1,
+            chunks[0].offset
+        ),
+        verified.problems[0].1.to_string()
+    );
+    assert_eq!(chunks[1].id, verified.problems[1].0.id);
+
+    // reading the stream notices, too
+    let mut out = Vec::new();
+    let err = ChunkSource::Local(root.clone())
+        .open(chunks)
+        .read_to_end(&mut out)
+        .unwrap_err();
+    assert!(err.to_string().contains("but the index says"), "{}", err);
+
+    Ok(())
+}
+
+#[test]
+fn archive() -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path().join("store.castr");
+    let mut store = ChunkStore::new(&root)?;
+    let tree = dir.path().join("tree");
+    fs::create_dir_all(tree.join("sub"))?;
+    fs::write(tree.join("a"), b"hello")?;
+    fs::write(tree.join("sub/b"), b"world")?;
+    let index = common::publish(&mut store, &tree, IndexKind::Catar, 1024)?;
+    let castr = root.to_str().unwrap();
+
+    let verified = casync::tools::verify(castr, &index, true)?;
+    assert!(verified.is_ok(), "{:?}", verified.problems);
+    assert_eq!(Some(4), verified.items);
+
+    // "only local stores"
+    assert!(casync::tools::verify("http://localhost:1/castr", &index, false).is_err());
+
+    Ok(())
+}